@@ -0,0 +1,212 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Interpretation of the ANSI escape sequences terminal programs use.
+//!
+//! Bold and underline are printed as such, moving the cursor forward and back is done
+//! with spaces and backsteps. All other sequences, like colors, are removed.
+
+use crate::style::{Bold, Span, Style, Underline};
+
+const ESC: char = '\x1b';
+
+/// Filters a stream of text with escape sequences
+#[derive(Debug, Clone)]
+pub struct Ansi {
+    bold_mode: Bold,
+    underline_mode: Underline,
+    bold: bool,
+    underline: bool,
+    /// Start of a sequence that continues in the next chunk
+    pending: String,
+}
+
+/// Outcome of reading a sequence from the start of the text
+enum Sequence {
+    /// Control sequence with its parameters and final character, and the length of the whole sequence
+    Control(String, char, usize),
+    /// Any other sequence, which is skipped
+    Other(usize),
+    Incomplete,
+}
+
+fn parse_sequence(text: &str) -> Sequence {
+    let mut characters = text.char_indices().skip(1);
+
+    match characters.next() {
+        // Control Sequence Introducer, parameters and intermediates are followed by the final byte
+        Some((_, '[')) => {
+            for (index, c) in characters {
+                if ('\x40'..='\x7e').contains(&c) {
+                    return Sequence::Control(text[2..index].to_owned(), c, index + 1);
+                }
+            }
+            Sequence::Incomplete
+        }
+        // Operating system commands, terminated by BEL or ESC \
+        Some((_, ']' | 'P' | '_' | '^')) => {
+            let mut previous = ' ';
+            for (index, c) in characters {
+                if c == '\x07' || (previous == ESC && c == '\\') {
+                    return Sequence::Other(index + 1);
+                }
+                previous = c;
+            }
+            Sequence::Incomplete
+        }
+        // Character set selection and similar sequences with intermediate bytes
+        Some((_, ' '..='/')) => match characters.find(|(_, c)| !(' '..='/').contains(c)) {
+            Some((index, c)) => Sequence::Other(index + c.len_utf8()),
+            None => Sequence::Incomplete,
+        },
+        Some((index, c)) => Sequence::Other(index + c.len_utf8()),
+        None => Sequence::Incomplete,
+    }
+}
+
+impl Ansi {
+    pub fn new(bold: Bold, underline: Underline) -> Ansi {
+        Ansi {
+            bold_mode: bold,
+            underline_mode: underline,
+            bold: false,
+            underline: false,
+            pending: String::new(),
+        }
+    }
+
+    fn style(&self) -> Style {
+        Style {
+            bold: self.bold.then_some(self.bold_mode),
+            underline: self.underline.then_some(self.underline_mode),
+            ..Style::default()
+        }
+    }
+
+    /// Filter the next chunk of text.
+    /// A sequence that is cut off at the end is completed with the next chunk.
+    pub fn feed(&mut self, chunk: &str) -> Vec<Span> {
+        let mut text = std::mem::take(&mut self.pending);
+        text.push_str(chunk);
+
+        let mut spans: Vec<Span> = Vec::new();
+        let mut push = |c: char, style: Style| match spans.last_mut() {
+            Some(span) if span.style == style => span.text.push(c),
+            _ => spans.push(Span::new(&c.to_string(), style)),
+        };
+
+        let mut index = 0;
+        while let Some(c) = text[index..].chars().next() {
+            if c != ESC {
+                // Keep line breaks, tabs and backspaces, drop other control characters
+                if !c.is_control() || matches!(c, '\n' | '\t' | '\x08') {
+                    push(c, self.style());
+                }
+                index += c.len_utf8();
+                continue;
+            }
+
+            match parse_sequence(&text[index..]) {
+                Sequence::Control(parameters, command, length) => {
+                    let count = || parameters.parse::<usize>().unwrap_or(1).max(1);
+                    match command {
+                        'm' => self.select_graphic_rendition(&parameters),
+                        // Cursor forward and back, without changing the line
+                        'C' => (0..count()).for_each(|_| push(' ', Style::default())),
+                        'D' => (0..count()).for_each(|_| push('\x08', Style::default())),
+                        _ => {}
+                    }
+                    index += length;
+                }
+                Sequence::Other(length) => index += length,
+                Sequence::Incomplete => {
+                    self.pending = text[index..].to_owned();
+                    break;
+                }
+            }
+        }
+
+        spans
+    }
+
+    fn select_graphic_rendition(&mut self, parameters: &str) {
+        let number = |parameter: &str| parameter.parse::<u32>().unwrap_or(0);
+        let mut parameters = parameters.split(';');
+
+        while let Some(parameter) = parameters.next() {
+            // Sub-parameters are separated by colons and belong to the parameter before them
+            let mut parts = parameter.split(':');
+            let has_sub_parameters = parameter.contains(':');
+
+            match parts.next().map(number).unwrap_or(0) {
+                0 => {
+                    self.bold = false;
+                    self.underline = false;
+                }
+                1 => self.bold = true,
+                // 4:0 selects no underline, the other styles are all printed the same
+                4 => self.underline = parts.next().map(number) != Some(0),
+                21 => self.underline = true,
+                22 => self.bold = false,
+                24 => self.underline = false,
+                // Extended colors, their arguments must not be read as attributes
+                38 | 48 | 58 if !has_sub_parameters => match parameters.next().map(number) {
+                    Some(5) => {
+                        parameters.next();
+                    }
+                    Some(2) => {
+                        parameters.nth(2);
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn graphic_rendition_and_cursor_movement() {
+        use crate::ansi::Ansi;
+        use crate::style::{Bold, Span, Style, Underline};
+
+        let mut ansi = Ansi::new(Bold::DoublePrint, Underline::Line);
+        let bold = Style::bold(Bold::DoublePrint);
+
+        assert_eq!(
+            ansi.feed("\x1b[1;38;5;4mls\x1b[0m\x1b[2Ca\x1b]0;title\x07\x1b[1D_\x1b[4"),
+            vec![
+                Span::new("ls", bold),
+                Span::new("  a\x08_", Style::default()),
+            ]
+        );
+        assert_eq!(
+            ansi.feed("mb\n"),
+            vec![Span::new("b\n", Style::underline(Underline::Line))]
+        );
+    }
+
+    #[test]
+    fn colon_sub_parameters() {
+        use crate::ansi::Ansi;
+        use crate::style::{Bold, Span, Style, Underline};
+
+        let mut ansi = Ansi::new(Bold::DoublePrint, Underline::Line);
+        let bold = Style::bold(Bold::DoublePrint);
+        let both = Style {
+            underline: Some(Underline::Line),
+            ..bold
+        };
+
+        assert_eq!(
+            ansi.feed("\x1b[1mA\x1b[38:5:4mB\x1b[4:3mC\x1b[4:0mD"),
+            vec![
+                Span::new("AB", bold),
+                Span::new("C", both),
+                Span::new("D", bold),
+            ]
+        );
+    }
+}
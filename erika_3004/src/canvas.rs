@@ -0,0 +1,227 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use crate::estimate::{
+    ADVANCE_MS, CARRIAGE_RETURN_MS, CARRIAGE_RETURN_PER_COLUMN_MS, MM_PER_INCH, PAPER_STEP_MS,
+};
+use crate::paper::STEPS_PER_INCH;
+use crate::{ControlCode, LineSpacing, Pitch, PrintJob};
+
+/// Largest distance moved with a single MovePaper command
+const MAX_PAPER_MOVE: u32 = 240;
+
+/// Text placed at a fixed position
+#[derive(Debug, Clone)]
+struct Placement {
+    /// Horizontal position in half characters
+    x: u32,
+    /// Vertical position in paper steps of 1/240 inch
+    y: u32,
+    text: String,
+}
+
+/// A sheet of paper text can be placed on at arbitrary positions.
+///
+/// Positions are relative to where the print head is when printing starts,
+/// which is usually the left margin of the first line.
+/// The left margin is set to that position, so returning the carriage always leads back to it.
+/// At the end the carriage is returned and that margin is deleted again.
+/// The text is printed from top to bottom, as the paper can only be moved forward.
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    pitch: Pitch,
+    line_spacing: LineSpacing,
+    placements: Vec<Placement>,
+}
+
+impl Canvas {
+    pub fn new(pitch: Pitch, line_spacing: LineSpacing) -> Canvas {
+        Canvas {
+            pitch,
+            line_spacing,
+            placements: Vec::new(),
+        }
+    }
+
+    /// Place a single line of text at a character cell.
+    /// Lines are counted with the line spacing of the canvas.
+    pub fn place_text(&mut self, column: usize, line: usize, text: &str) {
+        self.placements.push(Placement {
+            x: column as u32 * 2,
+            y: line as u32 * self.line_spacing.paper_steps(),
+            text: text.to_owned(),
+        });
+    }
+
+    /// Place a single line of text at a position given in millimetres.
+    /// The position is rounded to the nearest half character horizontally.
+    pub fn place_text_mm(&mut self, x: f64, y: f64, text: &str) {
+        let half_cells = x / MM_PER_INCH * self.pitch.chars_per_inch() as f64 * 2.0;
+        let steps = y / MM_PER_INCH * STEPS_PER_INCH as f64;
+
+        self.placements.push(Placement {
+            x: half_cells.max(0.0).round() as u32,
+            y: steps.max(0.0).round() as u32,
+            text: text.to_owned(),
+        });
+    }
+
+    /// Plan the movements needed to print all text, and create a print job for them.
+    pub fn render(&self) -> PrintJob {
+        let mut job = PrintJob::empty();
+        job.push_control(self.pitch.control_code());
+        job.push_control(self.line_spacing.control_code());
+        // Moves that return the carriage expect Enter to lead to the origin
+        job.push_control(ControlCode::MarginSet);
+        job.mark_prologue();
+
+        let mut placements: Vec<&Placement> = self.placements.iter().collect();
+        placements.sort_by_key(|placement| (placement.y, placement.x));
+
+        let mut x = 0;
+        let mut y = 0;
+        for placement in placements {
+            self.push_movement(&mut job, (x, y), (placement.x, placement.y));
+            job.push_text(&placement.text);
+
+            x = placement.x + placement.text.chars().count() as u32 * 2;
+            y = placement.y;
+        }
+
+        // Return to the origin and remove the margin set there, so it does not outlast the job
+        if x > 0 {
+            job.push_control(ControlCode::Enter);
+        }
+        job.push_control(ControlCode::MarginDel);
+
+        job
+    }
+
+    /// Move from one position to another that is not above it, choosing whichever is faster:
+    /// moving the carriage directly, or returning it with Enter.
+    fn push_movement(&self, job: &mut PrintJob, from: (u32, u32), to: (u32, u32)) {
+        let line_steps = self.line_spacing.paper_steps();
+        let dy = to.1 - from.1;
+
+        let direct = horizontal_cost(from.0, to.0) + dy as f64 * PAPER_STEP_MS;
+        let with_return = if dy >= line_steps {
+            Some(
+                CARRIAGE_RETURN_MS
+                    + from.0 as f64 / 2.0 * CARRIAGE_RETURN_PER_COLUMN_MS
+                    + dy as f64 * PAPER_STEP_MS
+                    + horizontal_cost(0, to.0),
+            )
+        } else {
+            None
+        };
+
+        match with_return {
+            Some(cost) if cost <= direct => {
+                job.push_control(ControlCode::Enter);
+                push_paper_move(job, dy - line_steps);
+                push_horizontal_move(job, 0, to.0);
+            }
+            _ => {
+                push_paper_move(job, dy);
+                push_horizontal_move(job, from.0, to.0);
+            }
+        }
+    }
+}
+
+/// Time needed to move the carriage between two positions in half characters
+fn horizontal_cost(from: u32, to: u32) -> f64 {
+    let distance = from.abs_diff(to);
+    (distance / 2 + distance % 2) as f64 * ADVANCE_MS
+}
+
+/// Move the carriage without printing, positions are given in half characters
+pub(crate) fn push_horizontal_move(job: &mut PrintJob, from: u32, to: u32) {
+    let distance = from.abs_diff(to);
+    let forward = to > from;
+
+    for _ in 0..distance / 2 {
+        if forward {
+            job.push_text(" ");
+        } else {
+            job.push_control(ControlCode::Backstep);
+        }
+    }
+
+    if distance % 2 == 1 {
+        job.push_control(if forward {
+            ControlCode::HalfstepRight
+        } else {
+            ControlCode::HalfstepLeft
+        });
+    }
+}
+
+/// Move the paper forward by the given number of steps of 1/240 inch.
+/// Moves are split up so that none of them uses one of the invalid step counts.
+pub(crate) fn push_paper_move(job: &mut PrintJob, steps: u32) {
+    let mut remaining = steps;
+
+    while remaining > 0 {
+        let mut step = remaining.min(MAX_PAPER_MOVE);
+        if (2..=6).contains(&(remaining - step)) {
+            // Leave enough for a valid move afterwards
+            step -= 7;
+        }
+
+        if (2..=6).contains(&step) {
+            for _ in 0..step {
+                job.push_control_with_argument(ControlCode::MovePaper, 1);
+            }
+        } else {
+            job.push_control_with_argument(ControlCode::MovePaper, step as u8);
+        }
+
+        remaining -= step;
+    }
+}
+
+mod test {
+    #[test]
+    fn paper_moves_avoid_invalid_steps() {
+        use crate::canvas::push_paper_move;
+        use crate::PrintJob;
+
+        let mut job = PrintJob::empty();
+        push_paper_move(&mut job, 244);
+        push_paper_move(&mut job, 3);
+        assert_eq!(job.data(), b"\xA6\xE9\xA6\x0B\xA6\x01\xA6\x01\xA6\x01");
+    }
+
+    #[test]
+    fn render_placements_top_to_bottom() {
+        use crate::{Canvas, LineSpacing, Pitch};
+
+        let mut canvas = Canvas::new(Pitch::Chars10PerInch, LineSpacing::Single);
+        canvas.place_text(1, 2, "b");
+        canvas.place_text(30, 0, "a");
+
+        // Returning the carriage is faster than backstepping 30 characters
+        let mut expected = b"\x88\x85\x7E".to_vec();
+        expected.extend([0x71; 30]);
+        expected.extend(b"\x61\x77\xA6\x28\x71\x4E\x77\x7F");
+        assert_eq!(canvas.render().data(), expected);
+    }
+
+    #[test]
+    fn carriage_returns_to_origin() {
+        use crate::{Canvas, Cursor, LineSpacing, Pitch, PrintJob};
+
+        let mut canvas = Canvas::new(Pitch::Chars10PerInch, LineSpacing::Single);
+        canvas.place_text(30, 0, "a");
+        canvas.place_text(1, 2, "b");
+
+        // Printing starts away from the left margin
+        let mut cursor = Cursor::new();
+        cursor.feed(PrintJob::new("     ").data());
+        cursor.feed(canvas.render().data());
+        assert_eq!(cursor.column(), 5);
+        assert_eq!(cursor.left_margin(), 0);
+    }
+}
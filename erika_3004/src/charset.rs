@@ -0,0 +1,310 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Decoding of text sent in the character sets of other systems,
+//! and replacement of the characters that are not on the daisy wheel.
+
+use crate::{unprintable_characters, ErikaError, Result};
+
+/// Upper half of code page 437, the character set of the IBM PC
+const CP437: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+/// Characters of Windows-1252 between 0x80 and 0x9F, where ISO 8859-1 has control characters
+const WINDOWS_1252: &str = "€\u{fffd}‚ƒ„…†‡ˆ‰Š‹Œ\u{fffd}Ž\u{fffd}\u{fffd}‘’“”•–—˜™š›œ\u{fffd}žŸ";
+
+/// Characters of ISO 8859-15 that differ from ISO 8859-1
+const LATIN9: [(u8, char); 8] = [
+    (0xA4, '€'),
+    (0xA6, 'Š'),
+    (0xA8, 'š'),
+    (0xB4, 'Ž'),
+    (0xB8, 'ž'),
+    (0xBC, 'Œ'),
+    (0xBD, 'œ'),
+    (0xBE, 'Ÿ'),
+];
+
+/// Letters with diacritics the typewriter doesn't have, after the letter they are printed as
+const LETTERS: [(char, &str); 32] = [
+    ('A', "ÀÁÂÃÅĀĂĄ"),
+    ('a', "àáâãåāăą"),
+    ('C', "ÇĆĈĊČ"),
+    ('c', "ćĉċč"),
+    ('D', "ĎĐÐ"),
+    ('d', "ďđð"),
+    ('E', "ÈÉÊËĒĔĖĘĚ"),
+    ('e', "êëēĕėęě"),
+    ('G', "ĜĞĠĢ"),
+    ('g', "ĝğġģ"),
+    ('I', "ÌÍÎÏĨĪĬĮİ"),
+    ('i', "ìíîïĩīĭįı"),
+    ('L', "ĹĻĽĿŁ"),
+    ('l', "ĺļľŀł"),
+    ('N', "ÑŃŅŇ"),
+    ('n', "ñńņň"),
+    ('O', "ÒÓÔÕØŌŎŐ"),
+    ('o', "òóôõøōŏő"),
+    ('R', "ŔŖŘ"),
+    ('r', "ŕŗř"),
+    ('S', "ŚŜŞŠ"),
+    ('s', "śŝşš"),
+    ('T', "ŢŤ"),
+    ('t', "ţť"),
+    ('U', "ÙÚÛŨŪŬŮŰŲ"),
+    ('u', "ùúûũūŭůűų"),
+    ('Y', "ÝŸ"),
+    ('y', "ýÿ"),
+    ('Z', "ŹŻŽ"),
+    ('z', "źżž"),
+    ('K', "Ķ"),
+    ('k', "ķ"),
+];
+
+/// Other characters that can be written with similar looking characters of the typewriter
+const REPLACEMENTS: [(&str, &str); 24] = [
+    ("‘’‚‛′‹›", "'"),
+    ("“”„‟″«»", "\""),
+    ("‐‑‒–—―−~", "-"),
+    ("\u{a0}\u{2002}\u{2003}\u{2009}\u{200a}\u{202f}", " "),
+    ("[{", "("),
+    ("]}", ")"),
+    ("…", "..."),
+    ("•∙", "*"),
+    ("·", "."),
+    ("×", "x"),
+    ("÷", ":"),
+    ("µ", "μ"),
+    ("©", "(C)"),
+    ("®", "(R)"),
+    ("™", "TM"),
+    ("½", "1/2"),
+    ("¼", "1/4"),
+    ("¾", "3/4"),
+    ("Æ", "AE"),
+    ("æ", "ae"),
+    ("Œ", "OE"),
+    ("œ", "oe"),
+    ("ﬁ", "fi"),
+    ("ﬂ", "fl"),
+];
+
+/// Character sets text can be received in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    /// Little endian, unless the text starts with a byte order mark
+    Utf16,
+    /// ISO 8859-1
+    Latin1,
+    /// ISO 8859-15, Latin 1 with the euro sign
+    Latin9,
+    Windows1252,
+    /// Code page 437 of DOS
+    Cp437,
+}
+
+impl Encoding {
+    pub const NAMES: [&'static str; 6] = [
+        "utf-8",
+        "utf-16",
+        "iso-8859-1",
+        "iso-8859-15",
+        "windows-1252",
+        "cp437",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16" | "utf16" => Some(Encoding::Utf16),
+            "iso-8859-1" | "latin1" => Some(Encoding::Latin1),
+            "iso-8859-15" | "latin9" => Some(Encoding::Latin9),
+            "windows-1252" | "cp1252" => Some(Encoding::Windows1252),
+            "cp437" | "ibm437" => Some(Encoding::Cp437),
+            _ => None,
+        }
+    }
+
+    /// Guess the character set of the data.
+    /// Unicode is recognized by its byte order mark, or UTF-8 by being valid.
+    /// Anything else is assumed to be in the fallback character set.
+    pub fn detect(data: &[u8], fallback: Encoding) -> Encoding {
+        if data.starts_with(b"\xFF\xFE") || data.starts_with(b"\xFE\xFF") {
+            Encoding::Utf16
+        } else if std::str::from_utf8(data).is_ok() {
+            Encoding::Utf8
+        } else {
+            fallback
+        }
+    }
+
+    /// Convert the data to Unicode. Invalid sequences are replaced by U+FFFD.
+    pub fn decode(self, data: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => {
+                let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+                String::from_utf8_lossy(data).into_owned()
+            }
+            Encoding::Utf16 => {
+                let (data, big_endian) = match data {
+                    [0xFE, 0xFF, rest @ ..] => (rest, true),
+                    [0xFF, 0xFE, rest @ ..] => (rest, false),
+                    _ => (data, false),
+                };
+                let units: Vec<u16> = data
+                    .chunks(2)
+                    .map(|pair| match (pair, big_endian) {
+                        ([high, low], true) | ([low, high], false) => {
+                            u16::from_be_bytes([*high, *low])
+                        }
+                        _ => 0xFFFD,
+                    })
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            Encoding::Latin1 => data.iter().map(|&byte| byte as char).collect(),
+            Encoding::Latin9 => data
+                .iter()
+                .map(
+                    |&byte| match LATIN9.iter().find(|(code, _)| *code == byte) {
+                        Some((_, c)) => *c,
+                        None => byte as char,
+                    },
+                )
+                .collect(),
+            Encoding::Windows1252 => data
+                .iter()
+                .map(|&byte| match byte {
+                    0x80..=0x9F => upper_half(WINDOWS_1252, byte - 0x80),
+                    _ => byte as char,
+                })
+                .collect(),
+            Encoding::Cp437 => data
+                .iter()
+                .map(|&byte| match byte {
+                    0x80..=0xFF => upper_half(CP437, byte - 0x80),
+                    _ => byte as char,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn upper_half(table: &str, index: u8) -> char {
+    table.chars().nth(index as usize).unwrap_or('\u{fffd}')
+}
+
+/// What happens to characters the typewriter can't print
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Substitution {
+    /// Print a question mark instead
+    QuestionMark,
+    /// Print a similar character, like the letter without its accent, or a question mark if there is none
+    Transliterate,
+    /// Refuse to print the text
+    Reject,
+}
+
+impl Substitution {
+    pub const NAMES: [&'static str; 3] = ["question-mark", "transliterate", "reject"];
+
+    pub fn from_name(name: &str) -> Option<Substitution> {
+        match name {
+            "question-mark" => Some(Substitution::QuestionMark),
+            "transliterate" => Some(Substitution::Transliterate),
+            "reject" => Some(Substitution::Reject),
+            _ => None,
+        }
+    }
+
+    /// Prepare text for printing.
+    /// Characters that remain unprintable are printed as question marks, unless the policy is to reject them.
+    pub fn apply(self, text: &str) -> Result<String> {
+        match self {
+            Substitution::QuestionMark => Ok(text.to_owned()),
+            Substitution::Transliterate => Ok(transliterate(text)),
+            Substitution::Reject => {
+                let unprintable = unprintable_characters(text);
+                if unprintable.is_empty() {
+                    Ok(text.to_owned())
+                } else {
+                    Err(ErikaError::UnprintableCharacters(unprintable))
+                }
+            }
+        }
+    }
+}
+
+/// Replace unprintable characters by similar ones
+fn transliterate(text: &str) -> String {
+    let unprintable = unprintable_characters(text);
+    if unprintable.is_empty() {
+        return text.to_owned();
+    }
+
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if !unprintable.contains(&c) {
+            result.push(c);
+        } else if let Some((letter, _)) = LETTERS.iter().find(|(_, accented)| accented.contains(c))
+        {
+            result.push(*letter);
+        } else if let Some((_, replacement)) = REPLACEMENTS
+            .iter()
+            .find(|(characters, _)| characters.contains(c))
+        {
+            result.push_str(replacement);
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+mod test {
+    #[test]
+    fn decode_legacy_encodings() {
+        use crate::Encoding;
+
+        let latin1 = b"Gr\xFC\xDFe \x80";
+        assert_eq!(Encoding::detect(latin1, Encoding::Latin1), Encoding::Latin1);
+        assert_eq!(Encoding::Latin1.decode(latin1), "Grüße \u{80}");
+        assert_eq!(Encoding::Windows1252.decode(latin1), "Grüße €");
+        assert_eq!(Encoding::Latin9.decode(b"\xA4"), "€");
+        assert_eq!(Encoding::Cp437.decode(b"\x81\xE1"), "üß");
+
+        let utf16 = b"\xFE\xFF\x00a\x00\xE4";
+        assert_eq!(Encoding::detect(utf16, Encoding::Latin1), Encoding::Utf16);
+        assert_eq!(Encoding::Utf16.decode(utf16), "aä");
+        assert_eq!(
+            Encoding::detect("ä".as_bytes(), Encoding::Latin1),
+            Encoding::Utf8
+        );
+    }
+
+    #[test]
+    fn substitute_unprintable_characters() {
+        use crate::{ErikaError, Substitution};
+
+        let text = "„Ça va?“ – [ok]";
+        assert_eq!(
+            Substitution::Transliterate.apply(text).unwrap(),
+            "\"Ca va?\" - (ok)"
+        );
+        assert_eq!(Substitution::QuestionMark.apply(text).unwrap(), text);
+        assert_eq!(
+            Substitution::Transliterate.apply("Æsop, Cæsar").unwrap(),
+            "AEsop, Caesar"
+        );
+        assert!(matches!(
+            Substitution::Reject.apply(text),
+            Err(ErikaError::UnprintableCharacters(_))
+        ));
+        assert!(crate::unprintable_characters(
+            &Substitution::Transliterate.apply("é à ł").unwrap()
+        )
+        .is_empty());
+    }
+}
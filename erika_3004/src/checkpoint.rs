@@ -0,0 +1,103 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use std::fs;
+use std::path::Path;
+
+use crate::{ErikaError, Result};
+
+const HEADER: &str = "erika-checkpoint 1";
+
+/// Progress of a print job, as far as it was acknowledged by the typewriter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Identifies the encoded document the checkpoint belongs to
+    fingerprint: u64,
+    /// Number of encoded bytes that were transmitted
+    written: usize,
+}
+
+impl Checkpoint {
+    pub fn new(data: &[u8], written: usize) -> Checkpoint {
+        Checkpoint {
+            fingerprint: fingerprint(data),
+            written,
+        }
+    }
+
+    /// Number of encoded bytes that were transmitted before the checkpoint was taken
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    pub(crate) fn set_written(&mut self, written: usize) {
+        self.written = written;
+    }
+
+    /// Whether the checkpoint was taken while printing this data
+    pub fn matches(&self, data: &[u8]) -> bool {
+        self.fingerprint == fingerprint(data) && self.written <= data.len()
+    }
+
+    /// Read a checkpoint that was written by save
+    pub fn load(path: &Path) -> Result<Checkpoint> {
+        let content = fs::read_to_string(path)?;
+        let mut lines = content.lines();
+
+        if lines.next() != Some(HEADER) {
+            return Err(ErikaError::InvalidCheckpoint);
+        }
+
+        let mut next_number = || -> Result<u64> {
+            lines
+                .next()
+                .and_then(|line| line.trim().parse().ok())
+                .ok_or(ErikaError::InvalidCheckpoint)
+        };
+
+        let fingerprint = next_number()?;
+        let written = next_number()?
+            .try_into()
+            .map_err(|_| ErikaError::InvalidCheckpoint)?;
+
+        Ok(Checkpoint {
+            fingerprint,
+            written,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(
+            path,
+            format!("{}\n{}\n{}\n", HEADER, self.fingerprint, self.written),
+        )?;
+        Ok(())
+    }
+}
+
+/// FNV-1a hash of the data. Unlike the std hashers, this is guaranteed to be stable between builds.
+fn fingerprint(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+mod test {
+    #[test]
+    fn save_and_load() -> crate::Result<()> {
+        use crate::Checkpoint;
+
+        let path = std::env::temp_dir().join("erika-checkpoint-test");
+        let checkpoint = Checkpoint::new(b"\x61\x77\x4E", 2);
+        checkpoint.save(&path)?;
+
+        let loaded = Checkpoint::load(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(loaded, checkpoint);
+        assert!(loaded.matches(b"\x61\x77\x4E"));
+        assert!(!loaded.matches(b"\x61\x77\x57"));
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+
+use crate::ControlCode;
+
+/// Characters that print an accent without moving the carriage
+const DEAD_KEYS: [u8; 4] = [0x03, 0x19, 0x29, 0x2B];
+
+/// Model of the carriage of the typewriter, that follows encoded output to know
+/// where the print head is, and which margins and tab stops are set.
+///
+/// Positions are measured in half characters from the left end of the carriage.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cursor {
+    position: u32,
+    left_margin: u32,
+    right_margin: Option<u32>,
+    tab_stops: BTreeSet<u32>,
+    /// Set if the last byte was a control code that is followed by an argument
+    argument_pending: bool,
+}
+
+fn takes_argument(code: ControlCode) -> bool {
+    matches!(
+        code,
+        ControlCode::MovePaper
+            | ControlCode::Bell
+            | ControlCode::TabStep
+            | ControlCode::MoveTape
+            | ControlCode::KeyStrength
+            | ControlCode::BoudRate
+    )
+}
+
+impl Cursor {
+    /// Carriage at the left end, without margins or tab stops
+    pub fn new() -> Cursor {
+        Cursor::default()
+    }
+
+    /// Column of the print head, rounded down to whole characters
+    pub fn column(&self) -> usize {
+        (self.position / 2) as usize
+    }
+
+    /// Position of the print head in half characters
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    pub fn left_margin(&self) -> usize {
+        (self.left_margin / 2) as usize
+    }
+
+    pub fn right_margin(&self) -> Option<usize> {
+        self.right_margin.map(|margin| (margin / 2) as usize)
+    }
+
+    /// Columns of all tab stops
+    pub fn tab_stops(&self) -> Vec<usize> {
+        self.tab_stops
+            .iter()
+            .map(|stop| (stop / 2) as usize)
+            .collect()
+    }
+
+    /// Follow encoded output
+    pub fn feed(&mut self, data: &[u8]) {
+        for byte in data {
+            self.feed_byte(*byte);
+        }
+    }
+
+    fn feed_byte(&mut self, byte: u8) {
+        if self.argument_pending {
+            self.argument_pending = false;
+            return;
+        }
+
+        let code = match ControlCode::try_from(byte) {
+            Ok(code) => code,
+            // Everything else is a character or a space
+            Err(_) => {
+                if !DEAD_KEYS.contains(&byte) {
+                    self.position += 2;
+                }
+                return;
+            }
+        };
+
+        match code {
+            ControlCode::Backstep => self.position = self.position.saturating_sub(2),
+            ControlCode::HalfStepBack | ControlCode::HalfstepLeft => {
+                self.position = self.position.saturating_sub(1)
+            }
+            ControlCode::HalfstepRight => self.position += 1,
+            ControlCode::Enter => self.position = self.left_margin,
+            ControlCode::Tab => {
+                if let Some(stop) = self.tab_stops.range(self.position + 1..).next() {
+                    self.position = *stop;
+                }
+            }
+            ControlCode::TabSet => {
+                self.tab_stops.insert(self.position);
+            }
+            ControlCode::TabDel => {
+                self.tab_stops.remove(&self.position);
+            }
+            ControlCode::TabAllDel => self.tab_stops.clear(),
+            ControlCode::MarginSet => self.left_margin = self.position,
+            ControlCode::RightMarginOn => self.right_margin = Some(self.position),
+            ControlCode::MarginDel => {
+                if self.left_margin == self.position {
+                    self.left_margin = 0;
+                }
+                if self.right_margin == Some(self.position) {
+                    self.right_margin = None;
+                }
+            }
+            ControlCode::MarginAllDel => {
+                self.left_margin = 0;
+                self.right_margin = None;
+            }
+            ControlCode::Reset => *self = Cursor::new(),
+            code => self.argument_pending = takes_argument(code),
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn follow_tabs_and_margins() {
+        use crate::Cursor;
+
+        let mut cursor = Cursor::new();
+        // Two spaces, set margin, a, set tab, Enter, Tab, b
+        cursor.feed(b"\x71\x71\x7E\x61\x7A\x77\x79\x4E");
+        assert_eq!(cursor.left_margin(), 2);
+        assert_eq!(cursor.tab_stops(), vec![3]);
+        assert_eq!(cursor.column(), 4);
+
+        // Moving the paper doesn't move the carriage, even if the argument looks like a character
+        cursor.feed(b"\xA6\x61\x72\x81");
+        assert_eq!(cursor.position(), 5);
+    }
+}
@@ -0,0 +1,483 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Emulation of a subset of ESC/P, the printer language of Epson dot matrix printers.
+//!
+//! Supported are bold, underline, italics (printed underlined), pitch selection,
+//! line spacing, paper feeds, form feeds, tabs and horizontal positioning.
+//! Graphics and all other commands are skipped.
+//!
+//! A form feed is a page break: printing stops and waits for the next sheet to be inserted,
+//! like at the end of a page of text. The Formfeed code of the typewriter is not used,
+//! since the typewriter doesn't know where on the sheet it is, and can't tell how far to move the paper.
+
+use crate::canvas::{push_horizontal_move, push_paper_move};
+use crate::paper::STEPS_PER_INCH;
+use crate::style::{Bold, Span, Style, Underline};
+use crate::{ControlCode, Cursor, LineSpacing, Pitch, PrintJob};
+
+const ESC: u8 = 0x1B;
+
+/// Translates a stream of ESC/P data into print jobs
+#[derive(Debug, Clone)]
+pub struct Escp {
+    bold_mode: Bold,
+    underline_mode: Underline,
+    bold: bool,
+    underline: bool,
+    italic: bool,
+    pitch: Pitch,
+    condensed: bool,
+    /// Start of a command that continues in the next chunk
+    pending: Vec<u8>,
+    /// A carriage return was received, which is combined with a following line feed
+    carriage_return: bool,
+    /// Text that has not been added to a job yet
+    spans: Vec<Span>,
+    /// Position of the carriage, relative to the start of the line
+    cursor: Cursor,
+}
+
+/// Outcome of reading a command from the start of the data
+enum Command {
+    /// The command is complete and takes up this many bytes
+    Complete(usize),
+    /// More data is needed
+    Incomplete,
+}
+
+/// Length of the command at the start of data, which begins with ESC
+fn command_length(data: &[u8]) -> Command {
+    let parameter = |index: usize| data.get(index).copied();
+    let with_data = |length: Option<usize>| match length {
+        Some(length) if data.len() >= length => Command::Complete(length),
+        _ => Command::Incomplete,
+    };
+
+    let code = match parameter(1) {
+        Some(code) => code,
+        None => return Command::Incomplete,
+    };
+
+    match code {
+        // Lists of tab stops, terminated by NUL
+        b'D' | b'B' => match data[2..].iter().position(|byte| *byte == 0) {
+            Some(end) => Command::Complete(end + 3),
+            None => Command::Incomplete,
+        },
+        // Bit images with the number of columns in two bytes
+        b'K' | b'L' | b'Y' | b'Z' => with_data(
+            parameter(2)
+                .zip(parameter(3))
+                .map(|(low, high)| 4 + low as usize + 256 * high as usize),
+        ),
+        b'*' => with_data(parameter(2).zip(parameter(3)).zip(parameter(4)).map(
+            |((mode, low), high)| {
+                let bytes_per_column = match mode {
+                    32..=40 => 3,
+                    71..=73 => 6,
+                    _ => 1,
+                };
+                5 + (low as usize + 256 * high as usize) * bytes_per_column
+            },
+        )),
+        // The page length is given in inches if the first parameter is zero
+        b'C' => match parameter(2) {
+            Some(0) => with_data(Some(4)),
+            Some(_) => Command::Complete(3),
+            None => Command::Incomplete,
+        },
+        // Extended commands with the length of their parameters in two bytes
+        b'(' => with_data(
+            parameter(3)
+                .zip(parameter(4))
+                .map(|(low, high)| 5 + low as usize + 256 * high as usize),
+        ),
+        // Vertical format unit channels, terminated by NUL
+        b'b' => match data
+            .get(3..)
+            .and_then(|rest| rest.iter().position(|byte| *byte == 0))
+        {
+            Some(end) => Command::Complete(end + 4),
+            None => Command::Incomplete,
+        },
+        b'.' => raster_graphics_length(data),
+        b'X' | b':' => with_data(Some(5)),
+        b'$' | b'\\' | b'c' | b'e' | b'f' | b'?' => with_data(Some(4)),
+        b'-' | b'3' | b'A' | b'J' | b'j' | b'!' | b'W' | b'x' | b'k' | b'l' | b'Q' | b'N'
+        | b'S' | b'U' | b'w' | b'a' | b't' | b'R' | b'p' | b'q' | b'r' | b'I' | b'/' | b'+'
+        | b' ' | b'%' | b'i' | b's' | b'y' | 0x19 => with_data(Some(3)),
+        _ => Command::Complete(2),
+    }
+}
+
+/// Length of a raster graphics command, ESC . followed by the compression mode,
+/// the resolution, the number of rows and the number of dots per row in two bytes
+fn raster_graphics_length(data: &[u8]) -> Command {
+    const HEADER: usize = 8;
+    if data.len() < HEADER {
+        return Command::Incomplete;
+    }
+
+    let rows = data[5] as usize;
+    let dots = data[6] as usize + 256 * data[7] as usize;
+    let size = rows * dots.div_ceil(8);
+
+    match data[2] {
+        0 if data.len() >= HEADER + size => Command::Complete(HEADER + size),
+        // Run length encoded: a counter below 128 is followed by that many bytes plus one,
+        // a higher one by a single byte that is repeated 257 minus the counter times
+        1 => {
+            let mut index = HEADER;
+            let mut decoded = 0;
+            while decoded < size {
+                let counter = match data.get(index) {
+                    Some(counter) => *counter as usize,
+                    None => return Command::Incomplete,
+                };
+                if counter < 128 {
+                    index += counter + 2;
+                    decoded += counter + 1;
+                } else {
+                    index += 2;
+                    decoded += 257 - counter;
+                }
+            }
+
+            if data.len() >= index {
+                Command::Complete(index)
+            } else {
+                Command::Incomplete
+            }
+        }
+        0 => Command::Incomplete,
+        // Other modes have no data in the command itself
+        _ => Command::Complete(HEADER),
+    }
+}
+
+/// Line spacing of the typewriter that is closest to a distance in paper steps
+fn closest_line_spacing(steps: u32) -> LineSpacing {
+    [
+        LineSpacing::Single,
+        LineSpacing::OneAndAHalf,
+        LineSpacing::Double,
+    ]
+    .into_iter()
+    .min_by_key(|spacing| spacing.paper_steps().abs_diff(steps))
+    .expect("there are line spacings")
+}
+
+/// Character of code page 437, which ESC/P printers use by default
+fn cp437(byte: u8) -> char {
+    match byte {
+        0x20..=0x7E => byte as char,
+        0x15 => '§',
+        0x81 => 'ü',
+        0x82 => 'é',
+        0x84 => 'ä',
+        0x87 => 'ç',
+        0x8A => 'è',
+        0x8E => 'Ä',
+        0x94 => 'ö',
+        0x99 => 'Ö',
+        0x9A => 'Ü',
+        0x9C => '£',
+        0xE1 => 'ß',
+        0xE6 => 'μ',
+        0xF8 => '°',
+        0xFD => '²',
+        _ => '?',
+    }
+}
+
+impl Escp {
+    pub fn new(bold: Bold, underline: Underline) -> Escp {
+        Escp {
+            bold_mode: bold,
+            underline_mode: underline,
+            bold: false,
+            underline: false,
+            italic: false,
+            pitch: Pitch::Chars10PerInch,
+            condensed: false,
+            pending: Vec::new(),
+            carriage_return: false,
+            spans: Vec::new(),
+            cursor: Cursor::new(),
+        }
+    }
+
+    fn style(&self) -> Style {
+        Style {
+            bold: self.bold.then_some(self.bold_mode),
+            underline: (self.underline || self.italic).then_some(self.underline_mode),
+            ..Style::default()
+        }
+    }
+
+    fn current_pitch(&self) -> Pitch {
+        if self.condensed {
+            Pitch::Chars15PerInch
+        } else {
+            self.pitch
+        }
+    }
+
+    /// Translate the next chunk of data.
+    /// A command that is cut off at the end is completed with the next chunk.
+    pub fn feed(&mut self, chunk: &[u8]) -> PrintJob {
+        let mut job = PrintJob::empty();
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(chunk);
+
+        let mut index = 0;
+        while index < data.len() {
+            let byte = data[index];
+
+            if self.carriage_return && byte != b'\n' {
+                // A carriage return without line feed, the following text overprints the line
+                self.carriage_return = false;
+                self.flush(&mut job);
+                self.push(&mut job, |job, cursor| {
+                    push_horizontal_move(job, cursor.position(), 0)
+                });
+            }
+
+            match byte {
+                ESC => match command_length(&data[index..]) {
+                    Command::Complete(length) => {
+                        self.command(&mut job, &data[index..index + length]);
+                        index += length;
+                        continue;
+                    }
+                    Command::Incomplete => {
+                        self.pending = data[index..].to_vec();
+                        break;
+                    }
+                },
+                b'\r' => self.carriage_return = true,
+                b'\n' => {
+                    self.carriage_return = false;
+                    self.push_text("\n");
+                }
+                b'\t' => self.push_text("\t"),
+                0x08 => self.push_text("\x08"),
+                // Treated as the end of the sheet, see the module documentation
+                0x0C => {
+                    self.carriage_return = false;
+                    self.flush(&mut job);
+                    // The next sheet starts at the left margin
+                    self.push(&mut job, |job, cursor| {
+                        if cursor.position() != cursor.left_margin() as u32 * 2 {
+                            job.push_control(ControlCode::Enter);
+                        }
+                    });
+                    job.push_page_break();
+                }
+                // Condensed printing on and off
+                0x0F => self.set_pitch(&mut job, |escp| escp.condensed = true),
+                0x12 => self.set_pitch(&mut job, |escp| escp.condensed = false),
+                byte if byte < 0x20 || byte == 0x7F => {}
+                byte => {
+                    let mut buffer = [0; 4];
+                    self.push_text(cp437(byte).encode_utf8(&mut buffer));
+                }
+            }
+
+            index += 1;
+        }
+
+        self.flush(&mut job);
+        job
+    }
+
+    fn command(&mut self, job: &mut PrintJob, command: &[u8]) {
+        let parameter = |index: usize| command.get(index).copied().unwrap_or(0);
+
+        match command[1] {
+            b'@' => {
+                self.flush(job);
+                self.bold = false;
+                self.underline = false;
+                self.italic = false;
+                self.condensed = false;
+                self.pitch = Pitch::Chars10PerInch;
+                self.push(job, |job, _| {
+                    job.push_control(ControlCode::Chars10PerInch);
+                    job.push_control(ControlCode::Row1);
+                });
+            }
+            b'E' | b'G' => self.bold = true,
+            b'F' | b'H' => self.bold = false,
+            b'4' => self.italic = true,
+            b'5' => self.italic = false,
+            b'-' => self.underline = matches!(parameter(2), 1 | b'1'),
+            b'P' => self.set_pitch(job, |escp| escp.pitch = Pitch::Chars10PerInch),
+            b'M' => self.set_pitch(job, |escp| escp.pitch = Pitch::Chars12PerInch),
+            b'g' => self.set_pitch(job, |escp| escp.pitch = Pitch::Chars15PerInch),
+            b'!' => {
+                let mode = parameter(2);
+                self.bold = mode & 0x08 != 0;
+                self.underline = mode & 0x80 != 0;
+                self.italic = mode & 0x40 != 0;
+                self.set_pitch(job, |escp| {
+                    escp.condensed = mode & 0x04 != 0;
+                    escp.pitch = if mode & 0x01 != 0 {
+                        Pitch::Chars12PerInch
+                    } else {
+                        Pitch::Chars10PerInch
+                    };
+                });
+            }
+            b'0' => self.set_line_spacing(job, STEPS_PER_INCH / 8),
+            b'1' => self.set_line_spacing(job, STEPS_PER_INCH * 7 / 72),
+            b'2' => self.set_line_spacing(job, STEPS_PER_INCH / 6),
+            b'3' => self.set_line_spacing(job, parameter(2) as u32 * STEPS_PER_INCH / 216),
+            b'A' => self.set_line_spacing(job, parameter(2) as u32 * STEPS_PER_INCH / 72),
+            b'J' => {
+                let steps = parameter(2) as u32 * STEPS_PER_INCH / 216;
+                self.flush(job);
+                self.push(job, |job, _| push_paper_move(job, steps));
+            }
+            b'D' => {
+                let mut stops: Vec<u32> = command[2..command.len() - 1]
+                    .iter()
+                    .map(|column| *column as u32 * 2)
+                    .collect();
+                stops.sort_unstable();
+
+                self.flush(job);
+                self.push(job, |job, cursor| {
+                    let position = cursor.position();
+                    let mut current = position;
+
+                    job.push_control(ControlCode::TabAllDel);
+                    for stop in stops {
+                        push_horizontal_move(job, current, stop);
+                        job.push_control(ControlCode::TabSet);
+                        current = stop;
+                    }
+                    push_horizontal_move(job, current, position);
+                });
+            }
+            // Absolute position in 1/60 inch from the left margin
+            b'$' => {
+                let sixtieths = parameter(2) as u32 + 256 * parameter(3) as u32;
+                let target = sixtieths * self.current_pitch().chars_per_inch() * 2 / 60;
+                self.flush(job);
+                self.push(job, |job, cursor| {
+                    push_horizontal_move(job, cursor.position(), target)
+                });
+            }
+            // Relative position in 1/120 inch, signed
+            b'\\' => {
+                let offset = i16::from_le_bytes([parameter(2), parameter(3)]) as i32;
+                let half_cells = offset * self.current_pitch().chars_per_inch() as i32 * 2 / 120;
+                self.flush(job);
+                self.push(job, |job, cursor| {
+                    let target = (cursor.position() as i32 + half_cells).max(0) as u32;
+                    push_horizontal_move(job, cursor.position(), target)
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        let style = self.style();
+        match self.spans.last_mut() {
+            Some(span) if span.style == style => span.text.push_str(text),
+            _ => self.spans.push(Span::new(text, style)),
+        }
+    }
+
+    fn set_pitch(&mut self, job: &mut PrintJob, change: impl FnOnce(&mut Escp)) {
+        let before = self.current_pitch();
+        change(self);
+
+        let pitch = self.current_pitch();
+        if pitch != before {
+            self.flush(job);
+            self.push(job, |job, _| job.push_control(pitch.control_code()));
+        }
+    }
+
+    fn set_line_spacing(&mut self, job: &mut PrintJob, steps: u32) {
+        let spacing = closest_line_spacing(steps);
+        self.flush(job);
+        self.push(job, |job, _| job.push_control(spacing.control_code()));
+    }
+
+    /// Add output to the job, and follow it with the cursor
+    fn push(&mut self, job: &mut PrintJob, output: impl FnOnce(&mut PrintJob, &Cursor)) {
+        let length = job.data().len();
+        output(job, &self.cursor);
+        self.cursor.feed(&job.data()[length..]);
+    }
+
+    /// Add the collected text to the job
+    fn flush(&mut self, job: &mut PrintJob) {
+        let spans = std::mem::take(&mut self.spans);
+        if !spans.is_empty() {
+            self.push(job, |job, _| job.push_spans(&spans));
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn bold_pitch_and_form_feed() {
+        use crate::escp::Escp;
+        use crate::style::{Bold, Underline};
+
+        let mut escp = Escp::new(Bold::DoublePrint, Underline::Word);
+        // Bold a, 12 cpi, b, CR LF, form feed
+        let job = escp.feed(b"\x1bEa\x1bF\x1bMb\r\n\x0c");
+        assert_eq!(job.data(), b"\xA9\x61\xA9\x89\x4E\x77");
+
+        // A command split over two chunks
+        assert!(escp.feed(b"\x1b").data().is_empty());
+        assert_eq!(escp.feed(b"-\x01c").data(), b"\x57\x72\x01");
+    }
+
+    #[test]
+    fn carriage_return_overprints() {
+        use crate::escp::Escp;
+        use crate::style::{Bold, Underline};
+
+        let mut escp = Escp::new(Bold::DoublePrint, Underline::Word);
+        assert_eq!(escp.feed(b"ab\r__").data(), b"\x61\x4E\x72\x72\x01\x01");
+    }
+
+    #[test]
+    fn commands_with_parameters() {
+        use crate::escp::Escp;
+        use crate::style::{Bold, Underline};
+
+        let mut escp = Escp::new(Bold::DoublePrint, Underline::Word);
+        // Horizontal skip, font selection, a command with two bytes of length, raster graphics
+        let job = escp.feed(
+            b"\x1bf\x00\x05a\x1bX\x00\x30\x00b\x1b(U\x01\x00\x0Ac\x1b.\x00\x14\x14\x02\x09\x00WWXX",
+        );
+        assert_eq!(job.data(), b"\x61\x4E\x57");
+
+        // The raster data is still missing
+        assert!(escp
+            .feed(b"\x1b.\x01\x14\x14\x01\x10\x00\xFF")
+            .data()
+            .is_empty());
+        assert_eq!(escp.feed(b"\x00a").data(), b"\x61");
+    }
+
+    #[test]
+    fn form_feed_returns_the_carriage() {
+        use crate::escp::Escp;
+        use crate::style::{Bold, Underline};
+
+        let mut escp = Escp::new(Bold::DoublePrint, Underline::Word);
+        let job = escp.feed(b"ab\x0cc");
+        assert_eq!(job.data(), b"\x61\x4E\x77\x57");
+    }
+}
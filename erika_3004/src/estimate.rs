@@ -0,0 +1,147 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use std::time::Duration;
+
+use crate::{BoudRate, ControlCode, LineSpacing, Pitch};
+
+// Timings of the print mechanism, measured roughly with a stopwatch.
+// They are good enough to plan a job, not to predict it to the second.
+
+/// Rotating the daisy wheel and striking a character
+const STRIKE_MS: f64 = 95.0;
+/// Moving the carriage by one character without striking
+pub(crate) const ADVANCE_MS: f64 = 40.0;
+/// Fixed part of a carriage return, the rest depends on the distance
+pub(crate) const CARRIAGE_RETURN_MS: f64 = 250.0;
+/// Carriage return time per character cell travelled
+pub(crate) const CARRIAGE_RETURN_PER_COLUMN_MS: f64 = 6.0;
+/// Moving the paper by one step of 1/240 inch
+pub(crate) const PAPER_STEP_MS: f64 = 2.5;
+/// Columns between two standard tab stops
+const TAB_WIDTH: usize = 8;
+/// Bits transferred per byte: start bit, eight data bits and a stop bit
+const BITS_PER_BYTE: f64 = 10.0;
+
+pub(crate) const MM_PER_INCH: f64 = 25.4;
+
+/// Encoded space character, which moves the carriage without striking
+const SPACE: u8 = 0x71;
+
+/// Settings the estimate depends on
+#[derive(Debug, Clone, Copy)]
+pub struct PrintSettings {
+    pub baud_rate: BoudRate,
+    pub pitch: Pitch,
+    pub line_spacing: LineSpacing,
+}
+
+impl Default for PrintSettings {
+    fn default() -> PrintSettings {
+        PrintSettings {
+            baud_rate: BoudRate::Rate1200,
+            pitch: Pitch::Chars10PerInch,
+            line_spacing: LineSpacing::Single,
+        }
+    }
+}
+
+/// Expected duration and consumption of a print job
+#[derive(Debug, Clone, Copy)]
+pub struct Estimate {
+    pub duration: Duration,
+    /// Number of times the daisy wheel strikes the ribbon
+    pub strikes: usize,
+    /// Number of printed lines
+    pub lines: usize,
+    /// Length of paper fed through the machine, in millimetres
+    pub paper_mm: f64,
+    /// Length of ribbon used up by the strikes, in millimetres
+    pub ribbon_mm: f64,
+}
+
+/// Estimate how long printing the text takes, and how much paper and ribbon it needs.
+pub fn estimate(text: &str, settings: &PrintSettings) -> Estimate {
+    let ms_per_byte = BITS_PER_BYTE * 1000.0 / settings.baud_rate.bits_per_second() as f64;
+    let line_ms = settings.line_spacing.paper_steps() as f64 * PAPER_STEP_MS;
+
+    let mut milliseconds = 0.0;
+    let mut strikes = 0;
+    let mut lines = 0;
+    let mut column = 0;
+
+    // The typewriter buffers incoming data while it is busy, so for every action,
+    // either the transfer or the mechanics determine how long it takes.
+    let mut step = |bytes: usize, mechanics: f64| {
+        milliseconds += f64::max(bytes as f64 * ms_per_byte, mechanics);
+    };
+
+    for c in text.chars() {
+        match c {
+            '\n' => {
+                step(
+                    1,
+                    CARRIAGE_RETURN_MS + column as f64 * CARRIAGE_RETURN_PER_COLUMN_MS + line_ms,
+                );
+                lines += 1;
+                column = 0;
+            }
+            '\t' => {
+                let next_stop = (column / TAB_WIDTH + 1) * TAB_WIDTH;
+                step(1, (next_stop - column) as f64 * ADVANCE_MS);
+                column = next_stop;
+            }
+            '\r' => {}
+            c => {
+                let encoded = gdrascii_codec::encode_char(c)
+                    .or_else(|| gdrascii_codec::encode_char('?'))
+                    .expect("? is always part of the codec");
+
+                // Combined characters are printed as several strikes with backsteps in between,
+                // dead keys like ^ are followed by a space. Either way, the character takes up one cell.
+                let mut mechanics = 0.0;
+                for byte in encoded {
+                    if *byte == SPACE || *byte == ControlCode::Backstep as u8 {
+                        mechanics += ADVANCE_MS;
+                    } else {
+                        mechanics += STRIKE_MS;
+                        strikes += 1;
+                    }
+                }
+                column += 1;
+
+                step(encoded.len(), mechanics);
+            }
+        }
+    }
+
+    if column > 0 {
+        lines += 1;
+    }
+
+    let line_inches = settings.line_spacing.paper_steps() as f64 / 240.0;
+    let cell_inches = 1.0 / settings.pitch.chars_per_inch() as f64;
+
+    Estimate {
+        duration: Duration::from_millis(milliseconds.round() as u64),
+        strikes,
+        lines,
+        paper_mm: lines as f64 * line_inches * MM_PER_INCH,
+        ribbon_mm: strikes as f64 * cell_inches * MM_PER_INCH,
+    }
+}
+
+mod test {
+    #[test]
+    fn estimate_counts_strikes_and_lines() {
+        use crate::{estimate, PrintSettings};
+
+        let result = estimate("Hello World\n€ ^a\nend", &PrintSettings::default());
+        assert_eq!(result.lines, 3);
+        // 10 letters, two strikes for the euro sign, ^ and a, then three more letters
+        assert_eq!(result.strikes, 17);
+        assert!((result.paper_mm - 12.7).abs() < 0.01);
+        assert!(result.duration.as_secs() > 1);
+    }
+}
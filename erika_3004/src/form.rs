@@ -0,0 +1,215 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+
+use crate::estimate::MM_PER_INCH;
+use crate::layout::wrap;
+use crate::paper::STEPS_PER_INCH;
+use crate::style::Alignment;
+use crate::{Canvas, ErikaError, LineSpacing, Pitch, PrintJob, Result};
+
+/// Unit the positions in a form template are given in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    /// Characters horizontally, lines vertically. Fractions like 2.5 are allowed.
+    Cells,
+    Mm,
+}
+
+/// A box on the form that a value is printed into
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FormField {
+    pub x: f64,
+    pub y: f64,
+    /// Number of characters that fit into the field. Longer values are wrapped or cut off.
+    pub width: usize,
+    /// Number of lines the value may be wrapped onto
+    #[serde(default = "default_lines")]
+    pub lines: usize,
+    #[serde(default = "default_alignment")]
+    pub align: Alignment,
+}
+
+fn default_lines() -> usize {
+    1
+}
+
+fn default_alignment() -> Alignment {
+    Alignment::Left
+}
+
+fn default_unit() -> Unit {
+    Unit::Cells
+}
+
+fn default_pitch() -> u32 {
+    10
+}
+
+fn default_line_spacing() -> f64 {
+    1.0
+}
+
+/// Positions of the fields of a pre-printed form.
+///
+/// Templates are written in TOML:
+///
+/// ```toml
+/// unit = "mm"
+///
+/// [fields.name]
+/// x = 20
+/// y = 45
+/// width = 30
+///
+/// [fields.amount]
+/// x = 150
+/// y = 45
+/// width = 10
+/// align = "right"
+/// ```
+///
+/// Positions are measured from the position of the print head when printing starts,
+/// and the left margin of the typewriter is set to it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FormTemplate {
+    #[serde(default = "default_unit")]
+    pub unit: Unit,
+    /// Characters per inch, 10, 12 or 15
+    #[serde(default = "default_pitch")]
+    pub pitch: u32,
+    /// Line spacing, 1, 1.5 or 2
+    #[serde(default = "default_line_spacing")]
+    pub line_spacing: f64,
+    pub fields: BTreeMap<String, FormField>,
+}
+
+impl FormTemplate {
+    pub fn from_toml(text: &str) -> Result<FormTemplate> {
+        let template: FormTemplate =
+            toml::from_str(text).map_err(|e| ErikaError::InvalidTemplate(e.to_string()))?;
+
+        // Check these early, so printing can't fail later
+        template.pitch()?;
+        template.line_spacing()?;
+
+        Ok(template)
+    }
+
+    pub fn pitch(&self) -> Result<Pitch> {
+        match self.pitch {
+            10 => Ok(Pitch::Chars10PerInch),
+            12 => Ok(Pitch::Chars12PerInch),
+            15 => Ok(Pitch::Chars15PerInch),
+            other => Err(ErikaError::InvalidTemplate(format!(
+                "pitch must be 10, 12 or 15, not {}",
+                other
+            ))),
+        }
+    }
+
+    pub fn line_spacing(&self) -> Result<LineSpacing> {
+        if self.line_spacing == 1.0 {
+            Ok(LineSpacing::Single)
+        } else if self.line_spacing == 1.5 {
+            Ok(LineSpacing::OneAndAHalf)
+        } else if self.line_spacing == 2.0 {
+            Ok(LineSpacing::Double)
+        } else {
+            Err(ErikaError::InvalidTemplate(format!(
+                "line_spacing must be 1, 1.5 or 2, not {}",
+                self.line_spacing
+            )))
+        }
+    }
+
+    /// Names of the fields there is no value for
+    pub fn missing_fields<'a>(&'a self, values: &HashMap<String, String>) -> Vec<&'a str> {
+        self.fields
+            .keys()
+            .filter(|name| !values.contains_key(*name))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Create a print job that prints the values into their fields.
+    /// Fields without a value are left empty, values without a field are ignored.
+    pub fn fill(&self, values: &HashMap<String, String>) -> Result<PrintJob> {
+        let pitch = self.pitch()?;
+        let line_spacing = self.line_spacing()?;
+        let mut canvas = Canvas::new(pitch, line_spacing);
+
+        // Size of a character cell in millimetres
+        let cell_width = MM_PER_INCH / pitch.chars_per_inch() as f64;
+        let line_height = line_spacing.paper_steps() as f64 / STEPS_PER_INCH as f64 * MM_PER_INCH;
+
+        for (name, field) in &self.fields {
+            let value = match values.get(name) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let lines = wrap(value, field.width);
+            for (index, line) in lines.iter().take(field.lines).enumerate() {
+                let line = line.trim();
+                let free = field.width.saturating_sub(line.chars().count());
+                let offset = match field.align {
+                    Alignment::Left => 0,
+                    Alignment::Right => free,
+                    Alignment::Center => free / 2,
+                };
+
+                let (x, y) = match self.unit {
+                    Unit::Cells => (field.x * cell_width, field.y * line_height),
+                    Unit::Mm => (field.x, field.y),
+                };
+
+                canvas.place_text_mm(
+                    x + offset as f64 * cell_width,
+                    y + index as f64 * line_height,
+                    line,
+                );
+            }
+        }
+
+        Ok(canvas.render())
+    }
+}
+
+mod test {
+    #[test]
+    fn fill_right_aligned_field() -> crate::Result<()> {
+        use std::collections::HashMap;
+
+        use crate::FormTemplate;
+
+        let template = FormTemplate::from_toml(
+            r#"
+            [fields.amount]
+            x = 2
+            y = 0
+            width = 5
+            align = "right"
+            "#,
+        )?;
+
+        let values = HashMap::from([("amount".to_owned(), "12".to_owned())]);
+        assert!(template.missing_fields(&values).is_empty());
+
+        let job = template.fill(&values)?;
+        // Pitch, line spacing and the left margin at the origin, five spaces, the digits,
+        // then back to the origin to delete the margin again
+        assert_eq!(
+            job.data(),
+            b"\x88\x85\x7E\x71\x71\x71\x71\x71\x11\x10\x77\x7F"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,621 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::canvas;
+use crate::remote_mode;
+use crate::style::{self, Span, Style};
+use crate::{Checkpoint, ControlCode, ErikaError, InputEvent, Result, TypewriterInterface};
+
+/// Number of bytes sent between two checks for cancellation.
+/// The typewriter prints roughly ten characters per second, so this keeps the reaction time short.
+const DEFAULT_CHUNK_SIZE: usize = 8;
+
+/// How long to ring the bell when a new sheet of paper is needed
+const PAPER_BELL_DURATION: Duration = Duration::from_millis(500);
+
+/// How often to check for a key press while waiting for a new sheet
+const PAPER_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Handle that can be used to cancel a print job from another thread
+#[derive(Clone, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    /// Request the job to stop after the chunk that is currently being printed
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Handle that can be used to follow the progress of a print job from another thread
+#[derive(Clone, Default)]
+pub struct ProgressHandle {
+    written: Arc<AtomicUsize>,
+    waiting_for_paper: Arc<AtomicBool>,
+}
+
+impl ProgressHandle {
+    /// Number of encoded bytes that have been sent to the typewriter
+    pub fn written(&self) -> usize {
+        self.written.load(Ordering::SeqCst)
+    }
+
+    /// Whether the job is waiting for a key press after a new sheet was inserted
+    pub fn is_waiting_for_paper(&self) -> bool {
+        self.waiting_for_paper.load(Ordering::SeqCst)
+    }
+}
+
+/// How a print job ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobOutcome {
+    Completed,
+    /// The job was aborted, after the given number of encoded bytes had been printed
+    Cancelled {
+        written: usize,
+    },
+}
+
+/// How a print job ended, and what went wrong without stopping it
+#[derive(Debug)]
+pub struct JobReport {
+    pub outcome: JobOutcome,
+    /// Why the progress could not be recorded. The job kept printing, but can't be resumed.
+    pub checkpoint_error: Option<ErikaError>,
+}
+
+/// Encoded output that is sent to the typewriter in small chunks, so it can be aborted at any time.
+pub struct PrintJob {
+    data: Vec<u8>,
+    /// Offsets in data after which the output can be interrupted without splitting a character or command
+    boundaries: Vec<usize>,
+    /// Offsets in data at which a new line starts
+    line_starts: Vec<usize>,
+    /// Offsets in data at which a new sheet of paper needs to be inserted
+    page_breaks: Vec<usize>,
+    /// Length of the settings at the start of data, which are sent again when a job is resumed
+    prologue: usize,
+    /// Offset in data printing starts at
+    start: usize,
+    checkpoint_path: Option<PathBuf>,
+    chunk_size: usize,
+    cancel: CancelHandle,
+    progress: ProgressHandle,
+    cancel_on_keypress: bool,
+    cancel_on_signal: bool,
+    reset_on_cancel: bool,
+}
+
+impl PrintJob {
+    /// Create an empty job
+    pub fn empty() -> PrintJob {
+        PrintJob {
+            data: Vec::new(),
+            boundaries: Vec::new(),
+            line_starts: Vec::new(),
+            page_breaks: Vec::new(),
+            prologue: 0,
+            start: 0,
+            checkpoint_path: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            cancel: CancelHandle::default(),
+            progress: ProgressHandle::default(),
+            cancel_on_keypress: false,
+            cancel_on_signal: false,
+            reset_on_cancel: false,
+        }
+    }
+
+    /// Create a job printing the given text
+    pub fn new(text: &str) -> PrintJob {
+        let mut job = PrintJob::empty();
+        job.push_text(text);
+        job
+    }
+
+    /// Append text. Line breaks, tabs and backspaces are translated to the respective control codes,
+    /// superscript and subscript characters that are not on the daisy wheel are printed
+    /// raised or lowered, other characters that are not part of the codec are printed as question marks.
+    pub fn push_text(&mut self, text: &str) {
+        for c in text.chars() {
+            match c {
+                '\n' => self.push_control(ControlCode::Enter),
+                '\t' => self.push_control(ControlCode::Tab),
+                '\x08' => self.push_control(ControlCode::Backstep),
+                '\r' => {}
+                c => match gdrascii_codec::encode_char(c) {
+                    Some(encoded) => self.push_encoded(encoded),
+                    None => match style::encode_script_character(c) {
+                        Some(encoded) => self.push_encoded(&encoded),
+                        None => self.push_text("?"),
+                    },
+                },
+            }
+        }
+    }
+
+    /// Append text with a style, like push_text
+    pub fn push_styled(&mut self, text: &str, style: &Style) {
+        self.push_spans(&[Span::new(text, *style)]);
+    }
+
+    /// Append runs of text with different styles
+    pub fn push_spans(&mut self, spans: &[Span]) {
+        style::push_spans(self, spans);
+    }
+
+    /// Append a control code
+    pub fn push_control(&mut self, code: ControlCode) {
+        self.push_encoded(&[code as u8]);
+
+        if let ControlCode::Enter = code {
+            self.line_starts.push(self.data.len());
+        }
+    }
+
+    /// Append a control code that takes an argument byte
+    pub fn push_control_with_argument(&mut self, code: ControlCode, argument: u8) {
+        self.push_encoded(&[code as u8, argument]);
+    }
+
+    /// Append the bell. Durations are rounded down to steps of 20ms, and can't be longer than 5.1s.
+    pub fn push_bell(&mut self, duration: Duration) -> Result<()> {
+        let steps = (duration.as_millis() / 20)
+            .try_into()
+            .map_err(|_| ErikaError::InvalidBellDuration)?;
+        self.push_control_with_argument(ControlCode::Bell, steps);
+        Ok(())
+    }
+
+    /// Move the paper forward by the given number of steps of 1/240 inch
+    pub fn push_paper_move(&mut self, steps: u32) {
+        canvas::push_paper_move(self, steps);
+    }
+
+    /// Stop printing at this point, ring the bell and wait for a key press before continuing.
+    /// If the key was the paper insertion key, the new sheet is pulled in.
+    pub fn push_page_break(&mut self) {
+        self.page_breaks.push(self.data.len());
+    }
+
+    /// Mark everything pushed so far as settings like the pitch or margins,
+    /// which are sent again before a resumed job continues
+    pub fn mark_prologue(&mut self) {
+        self.prologue = self.data.len();
+    }
+
+    /// Append bytes that are already encoded. They are never split when aborting.
+    pub fn push_encoded(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        self.data.extend_from_slice(data);
+        self.boundaries.push(self.data.len());
+    }
+
+    /// Append the output of another job
+    pub fn append(&mut self, other: &PrintJob) {
+        let offset = self.data.len();
+        let shift = |offsets: &[usize]| offsets.iter().map(move |o| o + offset).collect::<Vec<_>>();
+
+        self.boundaries.extend(shift(&other.boundaries));
+        self.line_starts.extend(shift(&other.line_starts));
+        self.page_breaks.extend(shift(&other.page_breaks));
+        self.data.extend_from_slice(&other.data);
+    }
+
+    /// The encoded output of the whole job
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Set the number of bytes sent between two checks for cancellation
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = chunk_size.max(1);
+    }
+
+    /// Abort the job as soon as a key is pressed on the typewriter
+    pub fn set_cancel_on_keypress(&mut self, enabled: bool) {
+        self.cancel_on_keypress = enabled;
+    }
+
+    /// Abort the job when the process receives SIGINT or SIGTERM, instead of terminating right away.
+    /// The process keeps running, so it can report how the job ended.
+    pub fn set_cancel_on_signal(&mut self, enabled: bool) {
+        self.cancel_on_signal = enabled;
+    }
+
+    /// Send a Reset to the typewriter after the job was aborted
+    pub fn set_reset_on_cancel(&mut self, enabled: bool) {
+        self.reset_on_cancel = enabled;
+    }
+
+    /// Record the progress in the given file while printing.
+    /// The file is removed once the job was printed completely.
+    /// If the file can't be written, the job continues without it, and the error is reported when it ends.
+    pub fn set_checkpoint_file(&mut self, path: &Path) {
+        self.checkpoint_path = Some(path.to_owned());
+    }
+
+    /// Continue after the last line that was printed completely before the checkpoint was taken.
+    /// Jobs with page breaks continue at the start of the last page instead, since it is printed on a fresh sheet.
+    /// The prologue is sent again before continuing.
+    pub fn resume(&mut self, checkpoint: &Checkpoint) -> Result<()> {
+        if !checkpoint.matches(&self.data) {
+            return Err(ErikaError::CheckpointMismatch);
+        }
+
+        let restarts = if self.page_breaks.is_empty() {
+            &self.line_starts
+        } else {
+            &self.page_breaks
+        };
+        let start = restarts
+            .iter()
+            .copied()
+            .take_while(|&start| start <= checkpoint.written())
+            .last()
+            .unwrap_or(0);
+
+        // Lines inside the prologue are started over from the beginning
+        self.start = if start <= self.prologue { 0 } else { start };
+        Ok(())
+    }
+
+    /// Offset in the encoded data printing starts at
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Handle that can be moved to another thread to cancel this job
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Handle that can be moved to another thread to follow the progress of this job
+    pub fn progress_handle(&self) -> ProgressHandle {
+        self.progress.clone()
+    }
+
+    /// Print the job.
+    /// The typewriter is in remote mode while printing, and the keyboard is re-enabled afterwards.
+    /// If the job is aborted, the carriage is returned to the start of the next line.
+    pub fn run(&self, interface: &mut TypewriterInterface) -> Result<JobReport> {
+        let previous_timeout = interface.read_timeout();
+        if self.cancel_on_keypress {
+            // Only check for pending input, never wait for it
+            interface.set_read_timeout(Duration::ZERO)?;
+        }
+
+        remote_mode::set_cancel_on_signal(self.cancel_on_signal);
+        let outcome = self.print(interface);
+        remote_mode::set_cancel_on_signal(false);
+
+        interface.set_read_timeout(previous_timeout)?;
+        outcome
+    }
+
+    fn print(&self, interface: &mut TypewriterInterface) -> Result<JobReport> {
+        let mut interface = interface.remote_mode()?;
+        let report = self.print_chunks(&mut interface);
+        let finished = interface.finish();
+
+        let report = report?;
+        finished?;
+        Ok(report)
+    }
+
+    fn print_chunks(&self, interface: &mut TypewriterInterface) -> Result<JobReport> {
+        let mut outcome = JobOutcome::Completed;
+        let mut written = self.start;
+        let mut checkpoint = Checkpoint::new(&self.data, written);
+        let mut checkpoint_path = self.checkpoint_path.as_deref();
+        let mut checkpoint_error = None;
+
+        // A resumed page is printed on a fresh sheet, which is inserted after the prologue
+        let mut at_page_break = self.start > 0 && self.page_breaks.contains(&self.start);
+        if self.start > 0 {
+            interface.write_encoded(&self.data[..self.prologue])?;
+        }
+
+        for end in self.chunk_ends() {
+            if at_page_break && !self.change_paper(interface)? {
+                outcome = self.cancel(interface, written)?;
+                break;
+            }
+
+            if self.should_cancel(interface)? {
+                outcome = self.cancel(interface, written)?;
+                break;
+            }
+
+            interface.write_encoded(&self.data[written..end])?;
+            written = end;
+            self.progress.written.store(written, Ordering::SeqCst);
+            at_page_break = self.page_breaks.contains(&written);
+
+            // Not being able to resume later is no reason to stop printing
+            if let Some(path) = checkpoint_path {
+                checkpoint.set_written(written);
+                if let Err(e) = checkpoint.save(path) {
+                    // An older checkpoint would match the job, and resume it at the wrong place
+                    let _ = fs::remove_file(path);
+                    checkpoint_error = Some(e);
+                    checkpoint_path = None;
+                }
+            }
+        }
+
+        if let (JobOutcome::Completed, Some(path)) = (outcome, checkpoint_path) {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(JobReport {
+            outcome,
+            checkpoint_error,
+        })
+    }
+
+    /// Leave the typewriter in a known state after the job was aborted
+    fn cancel(&self, interface: &mut TypewriterInterface, written: usize) -> Result<JobOutcome> {
+        interface.send_control(ControlCode::Enter)?;
+        if self.reset_on_cancel {
+            interface.send_control(ControlCode::Reset)?;
+        }
+
+        Ok(JobOutcome::Cancelled { written })
+    }
+
+    /// Ring the bell and wait for the next sheet of paper.
+    /// Returns false if the job was cancelled while waiting.
+    fn change_paper(&self, interface: &mut TypewriterInterface) -> Result<bool> {
+        interface.bell(PAPER_BELL_DURATION)?;
+        self.progress
+            .waiting_for_paper
+            .store(true, Ordering::SeqCst);
+        let inserted = self.wait_for_paper(interface);
+        self.progress
+            .waiting_for_paper
+            .store(false, Ordering::SeqCst);
+        inserted
+    }
+
+    /// Wait until a key is pressed on the typewriter.
+    /// Returns false if the job was cancelled while waiting.
+    fn wait_for_paper(&self, interface: &mut TypewriterInterface) -> Result<bool> {
+        loop {
+            if self.is_cancelled() {
+                return Ok(false);
+            }
+
+            match interface.read_character() {
+                Ok(Some(InputEvent::ControlCode(ControlCode::GetPaper))) => {
+                    interface.send_control(ControlCode::GetPaper)?;
+                    return Ok(true);
+                }
+                Ok(Some(_)) | Err(ErikaError::UnknownCode(_)) => return Ok(true),
+                Ok(None) => thread::sleep(PAPER_POLL_INTERVAL),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Whether the job was cancelled from another thread or by a signal
+    fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled() || remote_mode::cancelled_by_signal()
+    }
+
+    fn should_cancel(&self, interface: &mut TypewriterInterface) -> Result<bool> {
+        if self.is_cancelled() {
+            return Ok(true);
+        }
+
+        if self.cancel_on_keypress {
+            return match interface.read_character() {
+                Ok(Some(_)) | Err(ErikaError::UnknownCode(_)) => Ok(true),
+                Ok(None) => Ok(false),
+                Err(e) => Err(e),
+            };
+        }
+
+        Ok(false)
+    }
+
+    /// Offsets at which each chunk ends
+    fn chunk_ends(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut start = self.start;
+        self.boundaries.iter().copied().filter(move |&end| {
+            if end <= start {
+                false
+            } else if end - start >= self.chunk_size
+                || end == self.data.len()
+                || self.page_breaks.contains(&end)
+            {
+                start = end;
+                true
+            } else {
+                false
+            }
+        })
+    }
+}
+
+mod test {
+    #[test]
+    fn push_text_translates_line_breaks() {
+        use crate::PrintJob;
+
+        let job = PrintJob::new("ab\r\nc");
+        assert_eq!(job.data(), b"\x61\x4E\x77\x57");
+    }
+
+    #[test]
+    fn chunks_do_not_split_characters() {
+        use crate::PrintJob;
+
+        let mut job = PrintJob::new("a€b€");
+        job.set_chunk_size(2);
+
+        let ends: Vec<usize> = job.chunk_ends().collect();
+        assert_eq!(ends, vec![4, 8]);
+    }
+
+    #[test]
+    fn chunks_do_not_split_script_shifts() {
+        use crate::style::{Script, Style};
+        use crate::PrintJob;
+
+        let mut job = PrintJob::empty();
+        job.push_styled("ab", &Style::script(Script::Superscript));
+        job.push_text("a");
+        job.set_chunk_size(1);
+
+        let ends: Vec<usize> = job.chunk_ends().collect();
+        assert_eq!(ends, vec![4, 5]);
+    }
+
+    #[test]
+    fn resume_at_last_complete_line() -> crate::Result<()> {
+        use crate::{Checkpoint, PrintJob};
+
+        let mut job = PrintJob::new("ab\ncd\nef");
+        job.resume(&Checkpoint::new(job.data(), 5))?;
+        assert_eq!(job.start(), 3);
+
+        let ends: Vec<usize> = job.chunk_ends().collect();
+        assert_eq!(ends, vec![8]);
+        Ok(())
+    }
+
+    #[test]
+    fn resume_at_start_of_page() -> crate::Result<()> {
+        use crate::{Checkpoint, ControlCode, PrintJob};
+
+        let mut job = PrintJob::empty();
+        job.push_control(ControlCode::Chars12PerInch);
+        job.mark_prologue();
+        job.push_text("ab\n");
+        job.push_page_break();
+        job.push_text("cd\nef");
+
+        job.resume(&Checkpoint::new(job.data(), 8))?;
+        assert_eq!(job.start(), 4);
+
+        // Nothing was printed after the prologue yet
+        job.resume(&Checkpoint::new(job.data(), 1))?;
+        assert_eq!(job.start(), 0);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resume_sends_prologue() -> crate::Result<()> {
+        use nix::pty::openpty;
+        use nix::unistd::{read, ttyname};
+
+        use crate::{Checkpoint, ControlCode, PrintJob, TypewriterInterface};
+
+        let pty = openpty(None, None).map_err(std::io::Error::from)?;
+        let path = ttyname(pty.slave).map_err(std::io::Error::from)?;
+        let mut interface = TypewriterInterface::new(path.to_str().unwrap())?;
+
+        let mut job = PrintJob::empty();
+        job.push_control(ControlCode::Chars12PerInch);
+        job.mark_prologue();
+        job.push_text("ab\ncd\n");
+        job.resume(&Checkpoint::new(job.data(), 5))?;
+        job.run(&mut interface)?;
+
+        let mut sent = Vec::new();
+        let mut buffer = [0; 8];
+        while sent.last() != Some(&(ControlCode::KeyboardOn as u8)) {
+            let size = read(pty.master, &mut buffer).map_err(std::io::Error::from)?;
+            sent.extend_from_slice(&buffer[..size]);
+        }
+
+        let mut expected = vec![ControlCode::KeyboardOff as u8];
+        expected.extend_from_slice(&job.data()[..1]);
+        expected.extend_from_slice(&job.data()[4..]);
+        expected.push(ControlCode::KeyboardOn as u8);
+        assert_eq!(sent, expected);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unwritable_checkpoint_does_not_stop_printing() -> crate::Result<()> {
+        use std::path::Path;
+
+        use nix::pty::openpty;
+        use nix::unistd::ttyname;
+
+        use crate::{JobOutcome, PrintJob, TypewriterInterface};
+
+        let pty = openpty(None, None).map_err(std::io::Error::from)?;
+        let path = ttyname(pty.slave).map_err(std::io::Error::from)?;
+        let mut interface = TypewriterInterface::new(path.to_str().unwrap())?;
+
+        let mut job = PrintJob::new("a long line that is printed in several chunks\n");
+        job.set_checkpoint_file(Path::new("/nonexistent/letter.txt.checkpoint"));
+        let report = job.run(&mut interface)?;
+        assert_eq!(report.outcome, JobOutcome::Completed);
+        assert!(report.checkpoint_error.is_some());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancel_while_waiting_for_paper() -> crate::Result<()> {
+        use std::thread;
+        use std::time::Duration;
+
+        use nix::pty::openpty;
+        use nix::unistd::{read, ttyname};
+
+        use crate::{ControlCode, JobOutcome, PrintJob, TypewriterInterface};
+
+        let pty = openpty(None, None).map_err(std::io::Error::from)?;
+        let path = ttyname(pty.slave).map_err(std::io::Error::from)?;
+        let mut interface = TypewriterInterface::new(path.to_str().unwrap())?;
+
+        let mut job = PrintJob::new("a");
+        job.push_page_break();
+        job.push_text("b");
+
+        let cancel = job.cancel_handle();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            cancel.cancel();
+        });
+        let report = job.run(&mut interface)?;
+        canceller.join().unwrap();
+        assert_eq!(report.outcome, JobOutcome::Cancelled { written: 1 });
+
+        // The carriage is returned like when a job is cancelled while printing
+        let mut sent = Vec::new();
+        let mut buffer = [0; 8];
+        while sent.last() != Some(&(ControlCode::KeyboardOn as u8)) {
+            let size = read(pty.master, &mut buffer).map_err(std::io::Error::from)?;
+            sent.extend_from_slice(&buffer[..size]);
+        }
+        assert_eq!(sent[sent.len() - 2], ControlCode::Enter as u8);
+        Ok(())
+    }
+}
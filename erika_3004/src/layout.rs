@@ -0,0 +1,306 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use crate::paper::STEPS_PER_INCH;
+use crate::{LineSpacing, Margins, PaperSize, Pitch, PrintJob};
+
+/// Placeholder in headers and footers that is replaced by the page number
+const PAGE_PLACEHOLDER: &str = "{page}";
+
+/// Describes how text is arranged on sheets of paper.
+/// All sizes are measured in lines and character cells.
+#[derive(Debug, Clone)]
+pub struct PageLayout {
+    pub pitch: Pitch,
+    pub line_spacing: LineSpacing,
+    /// Number of lines that fit on a sheet
+    pub page_length: usize,
+    /// Number of characters that fit on a line
+    pub page_width: usize,
+    pub margin_top: usize,
+    pub margin_bottom: usize,
+    pub margin_left: usize,
+    pub margin_right: usize,
+    /// Printed at the top of every page. {page} is replaced by the page number.
+    pub header: Option<String>,
+    /// Printed at the bottom of every page. {page} is replaced by the page number.
+    pub footer: Option<String>,
+    /// Print the page number centered below the text of every page
+    pub page_numbers: bool,
+    /// Program the left and right margin into the typewriter instead of indenting every line
+    pub hardware_margins: bool,
+}
+
+impl Default for PageLayout {
+    /// A4 paper with single line spacing and ten characters per inch
+    fn default() -> PageLayout {
+        PageLayout::new(&PaperSize::A4, Pitch::Chars10PerInch, LineSpacing::Single)
+    }
+}
+
+impl PageLayout {
+    /// Layout that fills the paper, with margins of one inch on all sides
+    pub fn new(paper: &PaperSize, pitch: Pitch, line_spacing: LineSpacing) -> PageLayout {
+        let margin_lines = (STEPS_PER_INCH / line_spacing.paper_steps()) as usize;
+        let margin_columns = pitch.chars_per_inch() as usize;
+
+        PageLayout {
+            pitch,
+            line_spacing,
+            page_length: paper.lines(line_spacing),
+            page_width: paper.columns(pitch),
+            margin_top: margin_lines,
+            margin_bottom: margin_lines,
+            margin_left: margin_columns,
+            margin_right: margin_columns,
+            header: None,
+            footer: None,
+            page_numbers: false,
+            hardware_margins: false,
+        }
+    }
+
+    /// Margins of the typewriter that match the left and right margin of the layout
+    pub fn margins(&self) -> Margins {
+        Margins {
+            pitch: self.pitch,
+            left: self.margin_left,
+            right: self.margin_left + self.text_width(),
+        }
+    }
+
+    /// Number of characters that fit between the margins
+    pub fn text_width(&self) -> usize {
+        self.page_width
+            .saturating_sub(self.margin_left + self.margin_right)
+            .max(1)
+    }
+
+    /// Lines taken by the header, including the blank line separating it from the text
+    fn header_length(&self) -> usize {
+        if self.header.is_some() {
+            2
+        } else {
+            0
+        }
+    }
+
+    /// Lines taken by the footer and page number, including the blank line separating them from the text
+    fn footer_length(&self) -> usize {
+        match (self.footer.is_some(), self.page_numbers) {
+            (false, false) => 0,
+            (true, true) => 3,
+            _ => 2,
+        }
+    }
+
+    /// Number of lines of text on each page
+    pub fn body_length(&self) -> usize {
+        self.page_length
+            .saturating_sub(
+                self.margin_top + self.margin_bottom + self.header_length() + self.footer_length(),
+            )
+            .max(1)
+    }
+
+    /// Wrap the text and distribute it on pages.
+    /// A form feed character in the text starts a new page.
+    pub fn paginate(&self, text: &str) -> Vec<Vec<String>> {
+        let mut pages = Vec::new();
+
+        for section in text.split('\x0c') {
+            let lines = wrap(section, self.text_width());
+            pages.extend(lines.chunks(self.body_length()).map(|page| page.to_vec()));
+        }
+
+        pages
+    }
+
+    /// Create a print job for the text that stops at the end of every sheet to wait for a new one
+    pub fn render(&self, text: &str) -> PrintJob {
+        let mut job = PrintJob::empty();
+        job.push_control(self.pitch.control_code());
+        job.push_control(self.line_spacing.control_code());
+
+        // Programming the margins returns the carriage twice, which takes the place of two empty lines
+        let mut programmed_lines = 0;
+        if self.hardware_margins {
+            job.append(&self.margins().render());
+            programmed_lines = 2;
+        }
+        job.mark_prologue();
+
+        let pages = self.paginate(text);
+
+        for (index, page) in pages.iter().enumerate() {
+            let number = index + 1;
+
+            if index > 0 {
+                job.push_page_break();
+            }
+
+            if index == 0 {
+                job.push_text(&"\n".repeat(self.margin_top.saturating_sub(programmed_lines)));
+            } else {
+                job.push_text(&"\n".repeat(self.margin_top));
+            }
+
+            if let Some(header) = &self.header {
+                self.push_line(
+                    &mut job,
+                    &header.replace(PAGE_PLACEHOLDER, &number.to_string()),
+                );
+                job.push_text("\n");
+            }
+
+            for line in page {
+                self.push_line(&mut job, line);
+            }
+
+            if self.footer_length() > 0 {
+                // Keep footers at the same height on all pages
+                job.push_text(&"\n".repeat(self.body_length() - page.len() + 1));
+
+                if let Some(footer) = &self.footer {
+                    self.push_line(
+                        &mut job,
+                        &footer.replace(PAGE_PLACEHOLDER, &number.to_string()),
+                    );
+                }
+
+                if self.page_numbers {
+                    let label = format!("- {} -", number);
+                    let padding = self.text_width().saturating_sub(label.chars().count()) / 2;
+                    self.push_line(&mut job, &format!("{}{}", " ".repeat(padding), label));
+                }
+            }
+        }
+
+        job
+    }
+
+    fn push_line(&self, job: &mut PrintJob, line: &str) {
+        let line = line.trim_end();
+        if !line.is_empty() {
+            if !self.hardware_margins {
+                job.push_text(&" ".repeat(self.margin_left));
+            }
+            job.push_text(line);
+        }
+        job.push_text("\n");
+    }
+}
+
+/// Break text into lines of at most width characters.
+/// Lines are broken at spaces where possible, words that are too long are split.
+/// Spaces at the start of a line of the text are kept, so indentation is printed.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut line_length = 0;
+        // Whether the paragraph was already broken into several lines
+        let mut continued = false;
+
+        for (index, word) in paragraph.split(' ').enumerate() {
+            let mut word: Vec<char> = word.chars().collect();
+            // The space a line is broken at is dropped
+            let mut separator = index > 0 && !(continued && line_length == 0);
+
+            if line_length > 0 && line_length + 1 + word.len() > width {
+                lines.push(std::mem::take(&mut line));
+                line_length = 0;
+                continued = true;
+                separator = false;
+            }
+
+            if separator {
+                line.push(' ');
+                line_length += 1;
+            }
+
+            while word.len() > width - line_length {
+                let rest = word.split_off(width - line_length);
+                line.extend(word);
+                lines.push(std::mem::take(&mut line));
+                line_length = 0;
+                continued = true;
+                word = rest;
+            }
+
+            line_length += word.len();
+            line.extend(word);
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+mod test {
+    #[test]
+    fn wrap_at_spaces() {
+        use crate::layout::wrap;
+
+        assert_eq!(
+            wrap("The quick brown fox\njumps", 10),
+            vec!["The quick", "brown fox", "jumps"]
+        );
+        assert_eq!(wrap("abcdefghijkl mn", 5), vec!["abcde", "fghij", "kl mn"]);
+        assert_eq!(wrap("", 5), Vec::<String>::new());
+        assert_eq!(
+            wrap("    indented code\n  x", 40),
+            vec!["    indented code", "  x"]
+        );
+    }
+
+    #[test]
+    fn paginate_with_footer() {
+        use crate::{LineSpacing, PageLayout, Pitch};
+
+        let layout = PageLayout {
+            pitch: Pitch::Chars10PerInch,
+            line_spacing: LineSpacing::Single,
+            page_length: 6,
+            page_width: 10,
+            margin_top: 1,
+            margin_bottom: 1,
+            margin_left: 0,
+            margin_right: 0,
+            header: None,
+            footer: None,
+            page_numbers: true,
+            hardware_margins: false,
+        };
+
+        assert_eq!(layout.body_length(), 2);
+        let pages = layout.paginate("one\ntwo\nthree\x0cfour");
+        assert_eq!(pages, vec![vec!["one", "two"], vec!["three"], vec!["four"]]);
+    }
+
+    #[test]
+    fn hardware_margins_replace_indentation() -> crate::Result<()> {
+        use crate::{Cursor, PageLayout};
+
+        let mut layout = PageLayout {
+            hardware_margins: true,
+            ..PageLayout::default()
+        };
+        layout.margin_left = 10;
+        layout.margin_right = 10;
+
+        let job = layout.render("text");
+        let mut cursor = Cursor::new();
+        cursor.feed(job.data());
+        layout.margins().verify(&cursor)?;
+        assert_eq!(layout.margins().width(), layout.text_width());
+
+        // The line is not indented, it starts at the left margin after the top margin
+        assert!(job.data().ends_with(b"\x77\x56\x5A\x5F\x56\x77"));
+        Ok(())
+    }
+}
@@ -15,12 +15,56 @@ use num_enum::TryFromPrimitive;
 
 use gdrascii_codec::EncodingError;
 
+mod ansi;
+mod canvas;
+mod charset;
+mod checkpoint;
+mod cursor;
+mod escp;
+mod estimate;
+pub mod form;
+mod job;
+pub mod layout;
+mod margins;
+pub mod markdown;
+pub mod markup;
+pub mod merge;
+pub mod overstrike;
+pub mod paper;
+mod remote_mode;
+pub mod style;
+mod table;
+
+pub use ansi::Ansi;
+pub use canvas::Canvas;
+pub use charset::{Encoding, Substitution};
+pub use checkpoint::Checkpoint;
+pub use cursor::Cursor;
+pub use escp::Escp;
+pub use estimate::{estimate, Estimate, PrintSettings};
+pub use form::FormTemplate;
+pub use job::{CancelHandle, JobOutcome, JobReport, PrintJob, ProgressHandle};
+pub use layout::PageLayout;
+pub use margins::Margins;
+pub use markdown::Markdown;
+pub use markup::Markup;
+pub use paper::PaperSize;
+pub use remote_mode::RemoteModeGuard;
+pub use style::{Span, Style};
+pub use table::Table;
+
 #[derive(Debug)]
 pub enum ErikaError {
     IO(io::Error),
     Serial(serial::Error),
     UnknownCode(u8),
     InvalidBellDuration,
+    InvalidCheckpoint,
+    CheckpointMismatch,
+    InvalidTemplate(String),
+    InvalidMargins(String),
+    InvalidMarkup(String),
+    UnprintableCharacters(Vec<char>),
 }
 
 impl Display for ErikaError {
@@ -31,7 +75,13 @@ impl Display for ErikaError {
             IO(e) => e.fmt(fmt),
             Serial(e) => e.fmt(fmt),
             UnknownCode(code) => write!(fmt, "Data received should either be in the codec range or a control code. This may indicate a character missing in the codec implementation. Code was {}", code),
-            InvalidBellDuration => write!(fmt, "Bell duration can not be encoded in a u8")
+            InvalidBellDuration => write!(fmt, "Bell duration can not be encoded in a u8"),
+            InvalidCheckpoint => write!(fmt, "The checkpoint file could not be parsed"),
+            CheckpointMismatch => write!(fmt, "The checkpoint was recorded for a different document"),
+            InvalidTemplate(reason) => write!(fmt, "Invalid template: {}", reason),
+            InvalidMargins(reason) => write!(fmt, "Invalid margins: {}", reason),
+            InvalidMarkup(reason) => write!(fmt, "Invalid markup: {}", reason),
+            UnprintableCharacters(characters) => write!(fmt, "The typewriter can't print these characters: {}", characters.iter().collect::<String>()),
         }
     }
 }
@@ -50,8 +100,26 @@ impl From<serial::Error> for ErikaError {
 
 pub type Result<T> = std::result::Result<T, ErikaError>;
 
+/// Characters of the text that the typewriter can't print, in order of their first occurrence.
+/// Line breaks, tabs and backspaces are not included, they are sent as control codes.
+pub fn unprintable_characters(text: &str) -> Vec<char> {
+    let mut unprintable = Vec::new();
+    for c in text.chars() {
+        if !matches!(c, '\n' | '\r' | '\t' | '\x08')
+            && gdrascii_codec::encode_char(c).is_none()
+            && style::encode_script_character(c).is_none()
+            && !unprintable.contains(&c)
+        {
+            unprintable.push(c);
+        }
+    }
+
+    unprintable
+}
+
 /// Boud rates supported by the typewriter
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BoudRate {
     Rate1200 = 10,
     Rate2400 = 8,
@@ -60,6 +128,97 @@ pub enum BoudRate {
     Rate19200 = 1,
 }
 
+impl BoudRate {
+    pub fn bits_per_second(&self) -> u32 {
+        match self {
+            BoudRate::Rate1200 => 1200,
+            BoudRate::Rate2400 => 2400,
+            BoudRate::Rate4800 => 4800,
+            BoudRate::Rate9600 => 9600,
+            BoudRate::Rate19200 => 19200,
+        }
+    }
+}
+
+/// Horizontal character spacing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pitch {
+    Chars10PerInch,
+    Chars12PerInch,
+    Chars15PerInch,
+}
+
+impl Pitch {
+    /// Names of the pitches in characters per inch, as accepted by from_name
+    pub const NAMES: [&'static str; 3] = ["10", "12", "15"];
+
+    pub fn from_name(name: &str) -> Option<Pitch> {
+        match name {
+            "10" => Some(Pitch::Chars10PerInch),
+            "12" => Some(Pitch::Chars12PerInch),
+            "15" => Some(Pitch::Chars15PerInch),
+            _ => None,
+        }
+    }
+
+    pub fn chars_per_inch(&self) -> u32 {
+        match self {
+            Pitch::Chars10PerInch => 10,
+            Pitch::Chars12PerInch => 12,
+            Pitch::Chars15PerInch => 15,
+        }
+    }
+
+    /// Control code that selects this pitch
+    pub fn control_code(&self) -> ControlCode {
+        match self {
+            Pitch::Chars10PerInch => ControlCode::Chars10PerInch,
+            Pitch::Chars12PerInch => ControlCode::Chars12PerInch,
+            Pitch::Chars15PerInch => ControlCode::Chars15PerInch,
+        }
+    }
+}
+
+/// Vertical distance between two lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineSpacing {
+    Single,
+    OneAndAHalf,
+    Double,
+}
+
+impl LineSpacing {
+    /// Names of the line spacings in lines, as accepted by from_name
+    pub const NAMES: [&'static str; 3] = ["1", "1.5", "2"];
+
+    pub fn from_name(name: &str) -> Option<LineSpacing> {
+        match name {
+            "1" => Some(LineSpacing::Single),
+            "1.5" => Some(LineSpacing::OneAndAHalf),
+            "2" => Some(LineSpacing::Double),
+            _ => None,
+        }
+    }
+
+    /// Paper steps (1/240 inch) moved for each line
+    pub fn paper_steps(&self) -> u32 {
+        match self {
+            LineSpacing::Single => 40,
+            LineSpacing::OneAndAHalf => 60,
+            LineSpacing::Double => 80,
+        }
+    }
+
+    /// Control code that selects this line spacing
+    pub fn control_code(&self) -> ControlCode {
+        match self {
+            LineSpacing::Single => ControlCode::Row1,
+            LineSpacing::OneAndAHalf => ControlCode::Row1Point5,
+            LineSpacing::Double => ControlCode::Row2,
+        }
+    }
+}
+
 /// Possible control codes to send
 #[repr(u8)]
 #[derive(TryFromPrimitive, Debug, Clone, Copy)]
@@ -140,6 +299,8 @@ pub enum InputEvent {
 /// Interface for receiving and sending text to the typewriter
 pub struct TypewriterInterface {
     port: serial::SystemPort,
+    /// Whether a RemoteModeGuard keeps the typewriter in remote mode
+    remote_mode_guarded: bool,
 }
 
 impl TypewriterInterface {
@@ -153,19 +314,72 @@ impl TypewriterInterface {
             Ok(())
         })?;
 
-        Ok(TypewriterInterface { port })
+        Ok(TypewriterInterface {
+            port,
+            remote_mode_guarded: false,
+        })
+    }
+
+    /// Keep other processes from opening the serial device for as long as this interface exists.
+    /// Processes running as root can still open it.
+    #[cfg(unix)]
+    pub fn lock(&mut self) -> Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        // TIOCEXCL takes no argument and only changes a flag of the terminal
+        if unsafe { nix::libc::ioctl(self.port.as_raw_fd(), nix::libc::TIOCEXCL) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(())
     }
 
     /// Send a unicode encoded rust string to the typewriter. The data will be encoded with the proprietary codec before sending.
     /// Returns the number of bytes written
     pub fn write_unicode(&mut self, text: &str) -> io::Result<usize> {
-        self.port.write(&gdrascii_codec::encode(text))
+        let data = gdrascii_codec::encode(text);
+        remote_mode::without_interruption(|| self.port.write(&data))
+    }
+
+    /// Send text formatted with a style. Only returns once all of it has been transmitted.
+    pub fn write_styled(&mut self, text: &str, style: &Style) -> Result<()> {
+        self.write_spans(&[Span::new(text, *style)])
+    }
+
+    /// Send runs of text with different styles. Only returns once all of it has been transmitted.
+    pub fn write_spans(&mut self, spans: &[Span]) -> Result<()> {
+        let mut job = PrintJob::empty();
+        job.push_spans(spans);
+        self.write_encoded(job.data())
+    }
+
+    /// Send data that is already encoded for the typewriter.
+    /// Only returns once all of it has been transmitted.
+    pub fn write_encoded(&mut self, data: &[u8]) -> Result<()> {
+        remote_mode::without_interruption(|| self.port.write_all(data))?;
+        self.port.flush()?;
+        Ok(())
+    }
+
+    /// Set how long read_character waits for input before giving up
+    pub fn set_read_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.port.set_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// How long read_character waits for input before giving up
+    pub fn read_timeout(&self) -> Duration {
+        self.port.timeout()
     }
 
     /// Send a control code
     fn send_control(&mut self, code: ControlCode) -> io::Result<()> {
-        self.port.write_all(&[code as u8])?;
-        Ok(())
+        remote_mode::without_interruption(|| self.port.write_all(&[code as u8]))
+    }
+
+    /// Send a control code that is followed by an argument
+    fn send_control_with_argument(&mut self, code: ControlCode, argument: u8) -> io::Result<()> {
+        remote_mode::without_interruption(|| self.port.write_all(&[code as u8, argument]))
     }
 
     /// Read a character from a serial device. The character is decoded along the way.
@@ -203,8 +417,7 @@ impl TypewriterInterface {
         let time_code = (duration.as_millis() / 20).try_into(); // One step on the typewriter is 20ms
         match time_code {
             Ok(steps) => {
-                self.send_control(ControlCode::Bell)?;
-                self.port.write_all(&[steps])?;
+                self.send_control_with_argument(ControlCode::Bell, steps)?;
                 Ok(())
             }
             Err(_) => Err(ErikaError::InvalidBellDuration),
@@ -212,8 +425,7 @@ impl TypewriterInterface {
     }
 
     pub fn set_tab_size(&mut self, strength: u8) -> Result<()> {
-        self.send_control(ControlCode::TabStep)?;
-        self.port.write_all(&[strength])?;
+        self.send_control_with_argument(ControlCode::TabStep, strength)?;
         Ok(())
     }
 
@@ -222,8 +434,27 @@ impl TypewriterInterface {
     pub fn move_paper(&mut self, step: u8) -> Result<()> {
         assert!(!(2..=6).contains(&step));
 
-        self.send_control(ControlCode::MovePaper)?;
-        self.port.write_all(&[step as u8])?;
+        self.send_control_with_argument(ControlCode::MovePaper, step)?;
+        Ok(())
+    }
+
+    /// Replace all margins. This moves the paper by two lines.
+    pub fn set_margins(&mut self, margins: &Margins) -> Result<()> {
+        let job = margins.render();
+        let mut cursor = Cursor::new();
+        cursor.feed(job.data());
+        margins.verify(&cursor)?;
+
+        self.write_encoded(job.data())
+    }
+
+    /// Delete the margin the carriage is currently at, or all margins.
+    pub fn delete_margin(&mut self, all: bool) -> Result<()> {
+        if all {
+            self.send_control(ControlCode::MarginAllDel)?;
+        } else {
+            self.send_control(ControlCode::MarginDel)?;
+        }
         Ok(())
     }
 
@@ -238,4 +469,10 @@ impl TypewriterInterface {
         self.send_control(ControlCode::KeyboardOn)?;
         Ok(())
     }
+
+    /// Enable remote mode until the returned guard goes out of scope.
+    /// The keyboard is also re-enabled if the process is interrupted by SIGINT or SIGTERM.
+    pub fn remote_mode(&mut self) -> Result<RemoteModeGuard<'_>> {
+        RemoteModeGuard::new(self)
+    }
 }
@@ -0,0 +1,111 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use crate::estimate::MM_PER_INCH;
+use crate::{ControlCode, Cursor, ErikaError, PaperSize, Pitch, PrintJob, Result};
+
+/// Left and right margin of the typewriter, in columns from the left end of the carriage.
+///
+/// The paper is expected to be inserted at the left end of the carriage.
+/// After the margins are set, Enter returns the carriage to the left margin,
+/// and the typewriter stops at the right margin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Margins {
+    pub pitch: Pitch,
+    /// First column text is printed in
+    pub left: usize,
+    /// Column the carriage stops at, one after the last column text is printed in
+    pub right: usize,
+}
+
+impl Margins {
+    pub fn new(pitch: Pitch, left: usize, right: usize) -> Result<Margins> {
+        if left >= right {
+            return Err(ErikaError::InvalidMargins(format!(
+                "the left margin (column {}) needs to be left of the right margin (column {})",
+                left, right
+            )));
+        }
+
+        Ok(Margins { pitch, left, right })
+    }
+
+    /// Margins measured from the left and right edge of the paper, rounded to whole characters
+    pub fn from_mm(
+        paper: &PaperSize,
+        pitch: Pitch,
+        left_mm: f64,
+        right_mm: f64,
+    ) -> Result<Margins> {
+        let columns =
+            |mm: f64| (mm.max(0.0) / MM_PER_INCH * pitch.chars_per_inch() as f64).round() as usize;
+
+        let right = paper
+            .columns(pitch)
+            .checked_sub(columns(right_mm))
+            .ok_or_else(|| {
+                ErikaError::InvalidMargins(format!("{} mm is wider than the paper", right_mm))
+            })?;
+
+        Margins::new(pitch, columns(left_mm), right)
+    }
+
+    /// Number of characters that fit between the margins
+    pub fn width(&self) -> usize {
+        self.right - self.left
+    }
+
+    /// Create a print job that replaces all margins with these ones.
+    ///
+    /// The carriage is returned to find the left end, and again after setting the margins,
+    /// so this moves the paper by two lines.
+    pub fn render(&self) -> PrintJob {
+        let mut job = PrintJob::empty();
+        job.push_control(self.pitch.control_code());
+        job.push_control(ControlCode::MarginAllDel);
+        job.push_control(ControlCode::Enter);
+        job.push_text(&" ".repeat(self.left));
+        job.push_control(ControlCode::MarginSet);
+        job.push_text(&" ".repeat(self.width()));
+        job.push_control(ControlCode::RightMarginOn);
+        job.push_control(ControlCode::Enter);
+        job
+    }
+
+    /// Check that the margins of the cursor are these ones.
+    ///
+    /// The cursor only models the data it was fed, so this checks a print job, not the typewriter.
+    pub fn verify(&self, cursor: &Cursor) -> Result<()> {
+        if cursor.left_margin() != self.left || cursor.right_margin() != Some(self.right) {
+            return Err(ErikaError::InvalidMargins(format!(
+                "expected margins at columns {} and {}, but they are at {} and {:?}",
+                self.left,
+                self.right,
+                cursor.left_margin(),
+                cursor.right_margin()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+mod test {
+    #[test]
+    fn margins_of_25_mm() -> crate::Result<()> {
+        use crate::{Cursor, Margins, PaperSize, Pitch};
+
+        let margins = Margins::from_mm(&PaperSize::A4, Pitch::Chars10PerInch, 25.0, 25.0)?;
+        assert_eq!((margins.left, margins.right), (10, 72));
+        assert_eq!(margins.width(), 62);
+
+        let mut cursor = Cursor::new();
+        cursor.feed(margins.render().data());
+        margins.verify(&cursor)?;
+        assert_eq!(cursor.column(), 10);
+
+        assert!(Margins::new(Pitch::Chars10PerInch, 5, 5).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+
+use crate::style::{spans_width, wrap_spans, Bold, Span, Style, Underline};
+use crate::{PrintJob, Table};
+
+/// Indentation of the text inside block quotes, on both sides
+const QUOTE_INDENT: usize = 4;
+/// Indentation of code blocks
+const CODE_INDENT: usize = 4;
+/// Number of columns a tab in a code block is expanded to
+const CODE_TAB_WIDTH: usize = 4;
+
+/// Renders CommonMark documents into print jobs
+#[derive(Debug, Clone)]
+pub struct Markdown {
+    /// How strong text and headings are made bold
+    pub bold: Bold,
+    /// How emphasized text and headings are underlined
+    pub underline: Underline,
+    /// Number of characters on a line
+    pub width: usize,
+    /// Empty characters in front of every line
+    pub margin_left: usize,
+}
+
+/// A block that indents the blocks inside it
+#[derive(Debug)]
+struct Container {
+    /// Printed in front of the first line inside the container, like a list bullet
+    first: String,
+    /// Whether a line has been printed inside the container
+    used: bool,
+    /// Space kept free at the end of lines
+    right: usize,
+}
+
+impl Container {
+    fn indent(width: usize, right: usize) -> Container {
+        Container {
+            first: " ".repeat(width),
+            used: false,
+            right,
+        }
+    }
+}
+
+/// State while walking through the events of a document
+struct Renderer<'a> {
+    markdown: &'a Markdown,
+    job: PrintJob,
+    containers: Vec<Container>,
+    /// Counters of the enclosing lists, None for bullet lists
+    lists: Vec<Option<u64>>,
+    /// Text of the current paragraph, heading or list item
+    spans: Vec<Span>,
+    strong: usize,
+    emphasis: usize,
+    heading: Option<HeadingLevel>,
+    code: Option<String>,
+    /// Rows of the current table, and the row that is being read
+    table: Option<(Vec<Vec<String>>, Vec<String>)>,
+    /// Whether the next block is separated by an empty line
+    blank_line: bool,
+}
+
+impl Markdown {
+    pub fn new(width: usize) -> Markdown {
+        Markdown {
+            bold: Bold::DoublePrint,
+            underline: Underline::Line,
+            width,
+            margin_left: 0,
+        }
+    }
+
+    /// Create a print job for a document.
+    /// Lines start margin_left characters right of the left margin of the typewriter.
+    pub fn render(&self, text: &str) -> PrintJob {
+        let mut renderer = Renderer {
+            markdown: self,
+            job: PrintJob::empty(),
+            containers: Vec::new(),
+            lists: Vec::new(),
+            spans: Vec::new(),
+            strong: 0,
+            emphasis: 0,
+            heading: None,
+            code: None,
+            table: None,
+            blank_line: false,
+        };
+
+        let options = Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS;
+        for event in Parser::new_ext(text, options) {
+            renderer.handle(event);
+        }
+        renderer.flush();
+
+        renderer.job
+    }
+}
+
+impl Renderer<'_> {
+    fn style(&self) -> Style {
+        let (bold, underline) = match self.heading {
+            Some(HeadingLevel::H1) => (true, true),
+            Some(HeadingLevel::H2) => (true, false),
+            Some(_) => (false, true),
+            None => (self.strong > 0, self.emphasis > 0),
+        };
+
+        Style {
+            bold: bold.then_some(self.markdown.bold),
+            underline: underline.then_some(self.markdown.underline),
+            ..Style::default()
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if let Some(code) = &mut self.code {
+            code.push_str(text);
+        } else if let Some((_, row)) = &mut self.table {
+            if let Some(cell) = row.last_mut() {
+                cell.push_str(text);
+            }
+        } else {
+            let style = self.style();
+            match self.spans.last_mut() {
+                Some(span) if span.style == style => span.text.push_str(text),
+                _ => self.spans.push(Span::new(text, style)),
+            }
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) | Event::Code(text) => self.push_text(&text),
+            Event::SoftBreak => self.push_text(" "),
+            Event::HardBreak => self.push_text("\n"),
+            Event::Rule => {
+                self.flush();
+                self.start_block();
+                let width = self.width();
+                self.push_lines(&[vec![Span::new(&"-".repeat(width), Style::default())]]);
+                self.blank_line = true;
+            }
+            Event::TaskListMarker(checked) => self.push_text(if checked { "[x] " } else { "[ ] " }),
+            Event::FootnoteReference(label) => self.push_text(&format!("[{}]", label)),
+            Event::Html(_) => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {
+                self.flush();
+            }
+            Tag::Heading(level, _, _) => {
+                self.flush();
+                self.heading = Some(level);
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                self.containers
+                    .push(Container::indent(QUOTE_INDENT, QUOTE_INDENT));
+            }
+            Tag::CodeBlock(_) => {
+                self.flush();
+                self.code = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".to_owned(),
+                };
+
+                self.containers.push(Container {
+                    first: bullet,
+                    used: false,
+                    right: 0,
+                });
+            }
+            Tag::Table(_) => {
+                self.flush();
+                self.table = Some((Vec::new(), Vec::new()));
+            }
+            Tag::TableCell => {
+                if let Some((_, row)) = &mut self.table {
+                    row.push(String::new());
+                }
+            }
+            Tag::Emphasis => self.emphasis += 1,
+            Tag::Strong => self.strong += 1,
+            Tag::FootnoteDefinition(label) => {
+                self.flush();
+                self.push_text(&format!("[{}]: ", label));
+            }
+            Tag::TableHead
+            | Tag::TableRow
+            | Tag::Strikethrough
+            | Tag::Link(..)
+            | Tag::Image(..) => {}
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::FootnoteDefinition(_) => {
+                self.flush();
+                self.blank_line = true;
+            }
+            Tag::Heading(..) => {
+                self.flush();
+                self.heading = None;
+                self.blank_line = true;
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                self.containers.pop();
+                self.blank_line = true;
+            }
+            Tag::CodeBlock(_) => {
+                let code = self.code.take().unwrap_or_default();
+
+                self.start_block();
+                self.containers.push(Container::indent(CODE_INDENT, 0));
+                for line in code.lines() {
+                    let line = line.replace('\t', &" ".repeat(CODE_TAB_WIDTH));
+                    // Code is never wrapped, so it keeps its meaning
+                    self.push_lines(&[vec![Span::new(&line, Style::default())]]);
+                }
+                self.containers.pop();
+                self.blank_line = true;
+            }
+            Tag::List(_) => {
+                self.flush();
+                self.lists.pop();
+                self.blank_line = true;
+            }
+            Tag::Item => {
+                self.flush();
+                self.containers.pop();
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some((rows, row)) = &mut self.table {
+                    rows.push(std::mem::take(row));
+                }
+            }
+            Tag::Table(_) => {
+                if let Some((rows, _)) = self.table.take() {
+                    self.start_block();
+
+                    // An unused list bullet gets a line of its own, the table is indented below it
+                    let prefix = self.prefix();
+                    if !prefix.trim().is_empty() {
+                        self.job.push_text(prefix.trim_end());
+                        self.job.push_text("\n");
+                    }
+
+                    let mut table = Table::new(rows);
+                    table.header = true;
+                    table.right_align_numbers = true;
+                    table.indent = prefix.chars().count();
+                    self.job.append(&table.render());
+                    self.blank_line = true;
+                }
+            }
+            Tag::Emphasis => self.emphasis -= 1,
+            Tag::Strong => self.strong -= 1,
+            Tag::TableCell | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..) => {}
+        }
+    }
+
+    /// Separate a block from the previous one, if needed
+    fn start_block(&mut self) {
+        if self.blank_line {
+            self.job.push_text("\n");
+            self.blank_line = false;
+        }
+    }
+
+    /// Number of characters on a line inside the current containers
+    fn width(&self) -> usize {
+        let indent: usize = self
+            .containers
+            .iter()
+            .map(|container| container.first.chars().count() + container.right)
+            .sum();
+
+        self.markdown.width.saturating_sub(indent).max(1)
+    }
+
+    /// Print the collected text, wrapped to fit inside the containers
+    fn flush(&mut self) {
+        if spans_width(&self.spans) == 0 {
+            self.spans.clear();
+            return;
+        }
+
+        let spans = std::mem::take(&mut self.spans);
+        self.start_block();
+        let lines = wrap_spans(&spans, self.width());
+        self.push_lines(&lines);
+    }
+
+    /// Indentation of the next line by the margin and the containers,
+    /// with the list bullets that have not been printed yet
+    fn prefix(&mut self) -> String {
+        let mut prefix = " ".repeat(self.markdown.margin_left);
+        for container in &mut self.containers {
+            if container.used {
+                prefix.push_str(&" ".repeat(container.first.chars().count()));
+            } else {
+                prefix.push_str(&container.first);
+                container.used = true;
+            }
+        }
+
+        prefix
+    }
+
+    /// Print lines, indented by the containers. Unused list bullets are printed in front of the first line.
+    fn push_lines(&mut self, lines: &[Vec<Span>]) {
+        for line in lines {
+            let prefix = self.prefix();
+            if !line.is_empty() {
+                self.job.push_text(&prefix);
+                self.job.push_spans(line);
+            }
+            self.job.push_text("\n");
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn hanging_indent_in_lists() {
+        use crate::markdown::Markdown;
+        use crate::PrintJob;
+
+        let job = Markdown::new(10).render("Text\n\n- one two three\n- four\n\n> q\n");
+
+        let mut expected = PrintJob::empty();
+        expected.push_text("Text\n\n- one two\n  three\n- four\n\n    q\n");
+        assert_eq!(job.data(), expected.data());
+    }
+
+    #[test]
+    fn headings_and_emphasis() {
+        use crate::markdown::Markdown;
+        use crate::style::{Bold, Span, Style, Underline};
+        use crate::PrintJob;
+
+        let job = Markdown::new(20).render("## Title\n\nSome *text*\n\n    code  block\n");
+
+        let mut expected = PrintJob::empty();
+        expected.push_styled("Title", &Style::bold(Bold::DoublePrint));
+        expected.push_text("\n\n");
+        expected.push_spans(&[
+            Span::new("Some ", Style::default()),
+            Span::new("text", Style::underline(Underline::Line)),
+        ]);
+        expected.push_text("\n\n    code  block\n");
+        assert_eq!(job.data(), expected.data());
+    }
+
+    #[test]
+    fn tables_are_indented() {
+        use crate::markdown::Markdown;
+        use crate::{PrintJob, Table};
+
+        let markdown = Markdown {
+            margin_left: 2,
+            ..Markdown::new(20)
+        };
+        let job = markdown.render("x\n\n> | a | b |\n> |---|---|\n> | c | d |\n");
+
+        let mut table = Table::new(vec![
+            vec!["a".to_owned(), "b".to_owned()],
+            vec!["c".to_owned(), "d".to_owned()],
+        ]);
+        table.header = true;
+        table.right_align_numbers = true;
+        table.indent = 2 + 4;
+
+        let mut expected = PrintJob::empty();
+        expected.push_text("  x\n\n");
+        expected.append(&table.render());
+        assert_eq!(job.data(), expected.data());
+    }
+}
@@ -0,0 +1,209 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! A small markup language for styling text on the typewriter.
+//!
+//! | Markup          | Result                                   |
+//! |-----------------|------------------------------------------|
+//! | `*bold*`        | bold                                     |
+//! | `_underline_`   | underlined                               |
+//! | `^{sup}`        | superscript                              |
+//! | `_{sub}`        | subscript                                |
+//! | `{center}`      | at the start of a line, centers it       |
+//! | `{right}`       | at the start of a line, aligns it right  |
+//! | `\*`            | a literal `*`, works for all characters  |
+//!
+//! Bold and underline can span several lines, superscript and subscript end at the line break.
+
+use crate::style::{spans_width, wrap_spans, Alignment, Bold, Script, Span, Style, Underline};
+use crate::{ErikaError, PrintJob, Result};
+
+/// A line of markup, before it is wrapped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkupLine {
+    pub alignment: Alignment,
+    pub spans: Vec<Span>,
+}
+
+/// Renders markup into print jobs
+#[derive(Debug, Clone)]
+pub struct Markup {
+    /// How text between asterisks is made bold
+    pub bold: Bold,
+    /// How text between underscores is underlined
+    pub underline: Underline,
+    /// Number of characters on a line, used to wrap and align lines
+    pub width: usize,
+    /// Empty characters in front of every line
+    pub margin_left: usize,
+}
+
+impl Markup {
+    /// Markup that uses the double print mode for bold text, and underlines once per line
+    pub fn new(width: usize) -> Markup {
+        Markup {
+            bold: Bold::DoublePrint,
+            underline: Underline::Line,
+            width,
+            margin_left: 0,
+        }
+    }
+
+    pub fn parse(&self, text: &str) -> Result<Vec<MarkupLine>> {
+        let mut lines = Vec::new();
+        let mut style = Style::default();
+        // Lines bold and underlined text started on, to report them if they are not closed
+        let mut bold_start = 0;
+        let mut underline_start = 0;
+
+        for (number, line) in text.lines().enumerate() {
+            let (alignment, line) = if let Some(rest) = line.strip_prefix("{center}") {
+                (Alignment::Center, rest)
+            } else if let Some(rest) = line.strip_prefix("{right}") {
+                (Alignment::Right, rest)
+            } else {
+                (Alignment::Left, line)
+            };
+
+            let mut spans: Vec<Span> = Vec::new();
+            let mut push = |c: char, style: Style| match spans.last_mut() {
+                Some(span) if span.style == style => span.text.push(c),
+                _ => spans.push(Span::new(&c.to_string(), style)),
+            };
+
+            let mut characters = line.chars().peekable();
+            while let Some(c) = characters.next() {
+                match c {
+                    '\\' => {
+                        if let Some(escaped) = characters.next() {
+                            push(escaped, style);
+                        }
+                    }
+                    '^' | '_' if characters.peek() == Some(&'{') => {
+                        if style.script.is_some() {
+                            return Err(ErikaError::InvalidMarkup(format!(
+                                "line {}: superscript and subscript can't be nested",
+                                number + 1
+                            )));
+                        }
+
+                        characters.next();
+                        style.script = Some(if c == '^' {
+                            Script::Superscript
+                        } else {
+                            Script::Subscript
+                        });
+                    }
+                    '}' if style.script.is_some() => style.script = None,
+                    '*' => {
+                        bold_start = number;
+                        style.bold = match style.bold {
+                            Some(_) => None,
+                            None => Some(self.bold),
+                        }
+                    }
+                    '_' => {
+                        underline_start = number;
+                        style.underline = match style.underline {
+                            Some(_) => None,
+                            None => Some(self.underline),
+                        }
+                    }
+                    c => push(c, style),
+                }
+            }
+
+            if style.script.is_some() {
+                return Err(ErikaError::InvalidMarkup(format!(
+                    "line {}: missing }} after superscript or subscript",
+                    number + 1
+                )));
+            }
+
+            lines.push(MarkupLine { alignment, spans });
+        }
+
+        if style.bold.is_some() {
+            return Err(ErikaError::InvalidMarkup(format!(
+                "line {}: missing * after bold text",
+                bold_start + 1
+            )));
+        }
+        if style.underline.is_some() {
+            return Err(ErikaError::InvalidMarkup(format!(
+                "line {}: missing _ after underlined text",
+                underline_start + 1
+            )));
+        }
+
+        Ok(lines)
+    }
+
+    /// Create a print job for the markup.
+    /// Lines start margin_left characters right of the left margin of the typewriter.
+    pub fn render(&self, text: &str) -> Result<PrintJob> {
+        let mut job = PrintJob::empty();
+
+        for line in self.parse(text)? {
+            let wrapped = wrap_spans(&line.spans, self.width);
+            if wrapped.is_empty() {
+                job.push_text("\n");
+            }
+
+            for spans in wrapped {
+                let free = self.width.saturating_sub(spans_width(&spans));
+                let indentation = match line.alignment {
+                    Alignment::Left => 0,
+                    Alignment::Center => free / 2,
+                    Alignment::Right => free,
+                };
+
+                job.push_text(&" ".repeat(self.margin_left + indentation));
+                job.push_spans(&spans);
+                job.push_text("\n");
+            }
+        }
+
+        Ok(job)
+    }
+}
+
+mod test {
+    #[test]
+    fn parse_styles() -> crate::Result<()> {
+        use crate::markup::Markup;
+        use crate::style::{Alignment, Bold, Script, Span, Style, Underline};
+
+        let markup = Markup::new(20);
+        let lines = markup.parse("{right}*a* _b_ x^{2}_{i} \\*")?;
+        assert_eq!(lines[0].alignment, Alignment::Right);
+        assert_eq!(
+            lines[0].spans,
+            vec![
+                Span::new("a", Style::bold(Bold::DoublePrint)),
+                Span::new(" ", Style::default()),
+                Span::new("b", Style::underline(Underline::Line)),
+                Span::new(" x", Style::default()),
+                Span::new("2", Style::script(Script::Superscript)),
+                Span::new("i", Style::script(Script::Subscript)),
+                Span::new(" *", Style::default()),
+            ]
+        );
+
+        assert!(markup.parse("^{open").is_err());
+        assert!(markup.parse("*bold\nstill bold*").is_ok());
+        assert!(markup.parse("*open\n_closed_").is_err());
+        assert!(markup.parse("_open").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn center_lines() -> crate::Result<()> {
+        use crate::markup::Markup;
+
+        let job = Markup::new(5).render("{center}a")?;
+        assert_eq!(job.data(), b"\x71\x71\x61\x77");
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use std::collections::HashMap;
+
+use crate::{ErikaError, Result};
+
+/// Replace placeholders like {{name}} in the template with the respective values.
+/// Returns an error if there is no value for a placeholder.
+pub fn fill_placeholders(template: &str, values: &HashMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        let name = rest[start + 2..end].trim();
+        let value = values.get(name).ok_or_else(|| {
+            ErikaError::InvalidTemplate(format!("There is no value for {{{{{}}}}}", name))
+        })?;
+
+        out.push_str(&rest[..start]);
+        out.push_str(value);
+        rest = &rest[end + 2..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+mod test {
+    #[test]
+    fn fill_placeholders() -> crate::Result<()> {
+        use std::collections::HashMap;
+
+        use crate::merge::fill_placeholders;
+
+        let values = HashMap::from([
+            ("name".to_owned(), "Erika".to_owned()),
+            ("city".to_owned(), "Dresden".to_owned()),
+        ]);
+
+        assert_eq!(
+            fill_placeholders("Dear {{ name }} from {{city}}, {{", &values)?,
+            "Dear Erika from Dresden, {{"
+        );
+        assert!(fill_placeholders("{{street}}", &values).is_err());
+        Ok(())
+    }
+}
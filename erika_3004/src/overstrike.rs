@@ -0,0 +1,122 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Interpretation of the backspace sequences that `nroff`, `man` and `ul` use for formatting:
+//! `c\bc` is a bold c, `_\bc` an underlined c.
+
+use crate::style::{Bold, Span, Style, Underline};
+
+/// A character cell, with everything that was struck into it
+#[derive(Debug, Default)]
+struct Cell {
+    characters: Vec<char>,
+    bold: bool,
+    underline: bool,
+}
+
+impl Cell {
+    fn strike(&mut self, c: char) {
+        if self.characters.contains(&c) {
+            self.bold = true;
+        } else if c == '_' && !self.characters.is_empty() {
+            self.underline = true;
+        } else if self.characters == ['_'] {
+            self.characters = vec![c];
+            self.underline = true;
+        } else {
+            self.characters.push(c);
+        }
+    }
+
+    /// Characters that are struck on top of each other are separated by backspaces,
+    /// which push_text sends as Backstep
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for (index, c) in self.characters.iter().enumerate() {
+            if index > 0 {
+                text.push('\x08');
+            }
+            text.push(*c);
+        }
+        text
+    }
+}
+
+/// Convert text with backspace sequences into styled spans.
+/// Overstrikes of different characters, like `o\b+`, are kept as they are.
+pub fn parse(text: &str, bold: Bold, underline: Underline) -> Vec<Span> {
+    let mut cells: Vec<Cell> = Vec::new();
+    let mut overstrike = false;
+
+    for c in text.chars() {
+        match (c, cells.last_mut()) {
+            ('\x08', _) => overstrike = true,
+            (c, Some(cell)) if overstrike && c != '\n' => {
+                cell.strike(c);
+                overstrike = false;
+            }
+            (c, _) => {
+                cells.push(Cell {
+                    characters: vec![c],
+                    ..Cell::default()
+                });
+                overstrike = false;
+            }
+        }
+    }
+
+    let mut spans: Vec<Span> = Vec::new();
+    for cell in cells {
+        let style = Style {
+            bold: cell.bold.then_some(bold),
+            underline: cell.underline.then_some(underline),
+            ..Style::default()
+        };
+
+        match spans.last_mut() {
+            Some(span) if span.style == style => span.text.push_str(&cell.text()),
+            _ => spans.push(Span::new(&cell.text(), style)),
+        }
+    }
+
+    spans
+}
+
+mod test {
+    #[test]
+    fn man_page_formatting() {
+        use crate::overstrike::parse;
+        use crate::style::{Bold, Span, Style, Underline};
+
+        let bold = Style::bold(Bold::Overstrike);
+        let underline = Style::underline(Underline::Line);
+
+        assert_eq!(
+            parse(
+                "l\x08ls\x08s _\x08f\x08f_\x08i _\x08\n",
+                Bold::Overstrike,
+                Underline::Line
+            ),
+            vec![
+                Span::new("ls", bold),
+                Span::new(" ", Style::default()),
+                Span::new(
+                    "f",
+                    Style {
+                        bold: Some(Bold::Overstrike),
+                        underline: Some(Underline::Line),
+                        ..Style::default()
+                    }
+                ),
+                Span::new("i", underline),
+                Span::new(" _\n", Style::default()),
+            ]
+        );
+
+        assert_eq!(
+            parse("o\x08+", Bold::Overstrike, Underline::Line),
+            vec![Span::new("o\x08+", Style::default())]
+        );
+    }
+}
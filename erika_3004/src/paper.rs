@@ -0,0 +1,113 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use crate::{LineSpacing, Pitch};
+
+/// Paper steps (the unit of move_paper) per inch. Horizontal positions use the same unit,
+/// one character is 24 steps wide at 10 characters per inch, 20 at 12 and 16 at 15.
+pub const STEPS_PER_INCH: u32 = 240;
+
+const fn millimetres(mm: u32) -> u32 {
+    // Round to the nearest step
+    (mm * STEPS_PER_INCH * 10 + 127) / 254
+}
+
+const fn inches(numerator: u32, denominator: u32) -> u32 {
+    numerator * STEPS_PER_INCH / denominator
+}
+
+/// Physical size of a sheet of paper, in steps of 1/240 inch.
+/// The width is the edge that goes into the typewriter first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaperSize {
+    pub name: &'static str,
+    pub width: u32,
+    pub length: u32,
+}
+
+impl PaperSize {
+    pub const A4: PaperSize = PaperSize {
+        name: "a4",
+        width: millimetres(210),
+        length: millimetres(297),
+    };
+    pub const A5: PaperSize = PaperSize {
+        name: "a5",
+        width: millimetres(148),
+        length: millimetres(210),
+    };
+    pub const LETTER: PaperSize = PaperSize {
+        name: "letter",
+        width: inches(17, 2),
+        length: inches(11, 1),
+    };
+    pub const LEGAL: PaperSize = PaperSize {
+        name: "legal",
+        width: inches(17, 2),
+        length: inches(14, 1),
+    };
+    /// DL envelope, inserted with the long edge first
+    pub const ENVELOPE_DL: PaperSize = PaperSize {
+        name: "dl",
+        width: millimetres(220),
+        length: millimetres(110),
+    };
+    /// C6 envelope, inserted with the long edge first
+    pub const ENVELOPE_C6: PaperSize = PaperSize {
+        name: "c6",
+        width: millimetres(162),
+        length: millimetres(114),
+    };
+    /// Fanfold paper of 240 mm width, with a page length of 12 inches
+    pub const CONTINUOUS: PaperSize = PaperSize {
+        name: "continuous",
+        width: millimetres(240),
+        length: inches(12, 1),
+    };
+
+    pub const ALL: [PaperSize; 7] = [
+        PaperSize::A4,
+        PaperSize::A5,
+        PaperSize::LETTER,
+        PaperSize::LEGAL,
+        PaperSize::ENVELOPE_DL,
+        PaperSize::ENVELOPE_C6,
+        PaperSize::CONTINUOUS,
+    ];
+
+    /// Names of the predefined sizes, as accepted by from_name
+    pub fn names() -> [&'static str; 7] {
+        PaperSize::ALL.map(|paper| paper.name)
+    }
+
+    /// Look up one of the predefined sizes by its name, ignoring case
+    pub fn from_name(name: &str) -> Option<PaperSize> {
+        PaperSize::ALL
+            .into_iter()
+            .find(|paper| paper.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Number of characters that fit on a line
+    pub fn columns(&self, pitch: Pitch) -> usize {
+        (self.width * pitch.chars_per_inch() / STEPS_PER_INCH) as usize
+    }
+
+    /// Number of lines that fit on a sheet
+    pub fn lines(&self, line_spacing: LineSpacing) -> usize {
+        (self.length / line_spacing.paper_steps()) as usize
+    }
+}
+
+mod test {
+    #[test]
+    fn a4_cells() {
+        use crate::{LineSpacing, PaperSize, Pitch};
+
+        let a4 = PaperSize::from_name("A4").unwrap();
+        assert_eq!(a4.columns(Pitch::Chars10PerInch), 82);
+        assert_eq!(a4.columns(Pitch::Chars12PerInch), 99);
+        assert_eq!(a4.lines(LineSpacing::Single), 70);
+        assert_eq!(a4.lines(LineSpacing::Double), 35);
+    }
+}
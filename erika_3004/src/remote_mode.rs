@@ -0,0 +1,325 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use std::io;
+use std::ops::{Deref, DerefMut};
+
+use crate::{Result, TypewriterInterface};
+
+/// Keeps the typewriter in remote mode for as long as it is alive.
+///
+/// When the guard is dropped, the keyboard is connected to the print head again.
+/// This also happens if the process receives SIGINT or SIGTERM while the guard exists,
+/// so an aborted command never leaves the machine in a state where typing doesn't print.
+///
+/// Guards can be nested, only the outermost one switches remote mode off again.
+/// Errors can't be reported when the guard is dropped, finish reports them.
+pub struct RemoteModeGuard<'a> {
+    interface: &'a mut TypewriterInterface,
+    /// Whether this guard switched remote mode on, instead of a guard it was created inside of
+    outermost: bool,
+    /// Where the port is registered for the signal handlers
+    slot: Option<usize>,
+}
+
+impl<'a> RemoteModeGuard<'a> {
+    pub(crate) fn new(interface: &'a mut TypewriterInterface) -> Result<RemoteModeGuard<'a>> {
+        if interface.remote_mode_guarded {
+            return Ok(RemoteModeGuard {
+                interface,
+                outermost: false,
+                slot: None,
+            });
+        }
+
+        signal::install_handlers()?;
+        interface.enable_remote_mode()?;
+        interface.remote_mode_guarded = true;
+        let slot = signal::register(interface);
+
+        Ok(RemoteModeGuard {
+            interface,
+            outermost: true,
+            slot,
+        })
+    }
+
+    /// Connect the keyboard to the print head again, like dropping the guard does
+    pub fn finish(mut self) -> Result<()> {
+        self.release()
+    }
+
+    fn release(&mut self) -> Result<()> {
+        if !self.outermost {
+            return Ok(());
+        }
+        self.outermost = false;
+
+        if let Some(slot) = self.slot.take() {
+            signal::unregister(slot);
+        }
+        self.interface.remote_mode_guarded = false;
+        self.interface.disable_remote_mode()
+    }
+}
+
+impl Deref for RemoteModeGuard<'_> {
+    type Target = TypewriterInterface;
+
+    fn deref(&self) -> &TypewriterInterface {
+        self.interface
+    }
+}
+
+impl DerefMut for RemoteModeGuard<'_> {
+    fn deref_mut(&mut self) -> &mut TypewriterInterface {
+        self.interface
+    }
+}
+
+impl Drop for RemoteModeGuard<'_> {
+    fn drop(&mut self) {
+        // There is nothing sensible left to do if this fails, the port is most likely gone
+        let _ = self.release();
+    }
+}
+
+/// Let SIGINT and SIGTERM cancel the running job instead of terminating the process
+pub(crate) fn set_cancel_on_signal(enabled: bool) {
+    signal::set_cancel_on_signal(enabled);
+}
+
+/// Whether a signal was received while cancel_on_signal was enabled
+pub(crate) fn cancelled_by_signal() -> bool {
+    signal::cancelled_by_signal()
+}
+
+/// Write to the port of the typewriter. A signal that arrives in the meantime is handled afterwards,
+/// so the keyboard is never switched on between a control code and its argument.
+pub(crate) fn without_interruption<T>(write: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    signal::without_interruption(write)
+}
+
+#[cfg(unix)]
+mod signal {
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+
+    use nix::libc::c_int;
+    use nix::sys::signal::{raise, sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+    use crate::{ControlCode, TypewriterInterface};
+
+    /// File descriptors of the ports that are in remote mode, -1 marks a free slot.
+    /// Signal handlers can't allocate, so the number of ports is fixed.
+    static REMOTE_PORT_FDS: [AtomicI32; 4] = [
+        AtomicI32::new(-1),
+        AtomicI32::new(-1),
+        AtomicI32::new(-1),
+        AtomicI32::new(-1),
+    ];
+
+    /// Number of writes to a port that are in progress
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    /// Signal that is handled once the writes in progress have finished, or 0
+    static PENDING_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+    static HANDLERS_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+    /// Whether a signal cancels the running job, instead of terminating the process
+    static CANCEL_ON_SIGNAL: AtomicBool = AtomicBool::new(false);
+    static CANCELLED_BY_SIGNAL: AtomicBool = AtomicBool::new(false);
+
+    const SIGNALS: [Signal; 2] = [Signal::SIGINT, Signal::SIGTERM];
+
+    extern "C" fn restore_keyboard(signal: c_int) {
+        // The job stops after the current chunk, and disables remote mode itself
+        if CANCEL_ON_SIGNAL.load(Ordering::SeqCst) {
+            CANCELLED_BY_SIGNAL.store(true, Ordering::SeqCst);
+            return;
+        }
+
+        PENDING_SIGNAL.store(signal, Ordering::SeqCst);
+        if WRITES.load(Ordering::SeqCst) == 0 {
+            terminate_if_pending();
+        }
+    }
+
+    /// Re-enable the keyboard and terminate, if a signal arrived.
+    /// Only uses async-signal-safe functions, so it can be called from a signal handler.
+    fn terminate_if_pending() {
+        let signal = PENDING_SIGNAL.swap(0, Ordering::SeqCst);
+        if signal == 0 {
+            return;
+        }
+
+        for slot in &REMOTE_PORT_FDS {
+            let fd = slot.swap(-1, Ordering::SeqCst);
+            if fd >= 0 {
+                // write(2) is async-signal-safe, so this is fine to do from a signal handler
+                let _ = nix::unistd::write(fd, &[ControlCode::KeyboardOn as u8]);
+            }
+        }
+
+        // Terminate the way the process would have without our handler
+        if let Ok(signal) = Signal::try_from(signal) {
+            let default = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+            unsafe {
+                let _ = sigaction(signal, &default);
+            }
+            let _ = raise(signal);
+        }
+    }
+
+    pub fn install_handlers() -> io::Result<()> {
+        if HANDLERS_INSTALLED.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let action = SigAction::new(
+            SigHandler::Handler(restore_keyboard),
+            SaFlags::empty(),
+            SigSet::empty(),
+        );
+        for signal in SIGNALS {
+            // Safety: the handler only uses async-signal-safe functions
+            unsafe { sigaction(signal, &action) }.map_err(io::Error::from)?;
+        }
+
+        HANDLERS_INSTALLED.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Returns the slot the port was registered in, or None if all slots are taken
+    pub fn register(interface: &TypewriterInterface) -> Option<usize> {
+        let fd = interface.port.as_raw_fd();
+        REMOTE_PORT_FDS.iter().position(|slot| {
+            slot.compare_exchange(-1, fd, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        })
+    }
+
+    pub fn unregister(slot: usize) {
+        REMOTE_PORT_FDS[slot].store(-1, Ordering::SeqCst);
+    }
+
+    pub fn without_interruption<T>(write: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+        WRITES.fetch_add(1, Ordering::SeqCst);
+        // A handler that saw no writes in progress may be writing to the port right now
+        let result = if PENDING_SIGNAL.load(Ordering::SeqCst) == 0 {
+            write()
+        } else {
+            Err(io::ErrorKind::Interrupted.into())
+        };
+
+        if WRITES.fetch_sub(1, Ordering::SeqCst) == 1 {
+            terminate_if_pending();
+        }
+        result
+    }
+
+    pub fn set_cancel_on_signal(enabled: bool) {
+        CANCELLED_BY_SIGNAL.store(false, Ordering::SeqCst);
+        CANCEL_ON_SIGNAL.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn cancelled_by_signal() -> bool {
+        CANCELLED_BY_SIGNAL.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(not(unix))]
+mod signal {
+    use std::io;
+
+    use crate::TypewriterInterface;
+
+    pub fn install_handlers() -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn register(_interface: &TypewriterInterface) -> Option<usize> {
+        None
+    }
+
+    pub fn unregister(_slot: usize) {}
+
+    pub fn without_interruption<T>(write: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+        write()
+    }
+
+    pub fn set_cancel_on_signal(_enabled: bool) {}
+
+    pub fn cancelled_by_signal() -> bool {
+        false
+    }
+}
+
+mod test {
+    #[cfg(unix)]
+    #[test]
+    fn drop_enables_keyboard() -> crate::Result<()> {
+        use nix::pty::openpty;
+        use nix::unistd::{read, ttyname};
+
+        use crate::{ControlCode, TypewriterInterface};
+
+        // The pseudo terminal stands in for the serial port of the typewriter
+        let pty = openpty(None, None).map_err(std::io::Error::from)?;
+        let path = ttyname(pty.slave).map_err(std::io::Error::from)?;
+        let mut interface = TypewriterInterface::new(path.to_str().unwrap())?;
+
+        drop(interface.remote_mode()?);
+
+        let mut sent = Vec::new();
+        let mut buffer = [0; 8];
+        while sent.len() < 2 {
+            let size = read(pty.master, &mut buffer).map_err(std::io::Error::from)?;
+            sent.extend_from_slice(&buffer[..size]);
+        }
+        assert_eq!(
+            sent,
+            [
+                ControlCode::KeyboardOff as u8,
+                ControlCode::KeyboardOn as u8
+            ]
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn nested_guards_switch_remote_mode_once() -> crate::Result<()> {
+        use nix::pty::openpty;
+        use nix::unistd::{read, ttyname};
+
+        use crate::{ControlCode, TypewriterInterface};
+
+        let pty = openpty(None, None).map_err(std::io::Error::from)?;
+        let path = ttyname(pty.slave).map_err(std::io::Error::from)?;
+        let mut interface = TypewriterInterface::new(path.to_str().unwrap())?;
+
+        let mut guard = interface.remote_mode()?;
+        drop(guard.remote_mode()?);
+        guard.write_encoded(b"\x61")?;
+        drop(guard);
+
+        let mut sent = Vec::new();
+        let mut buffer = [0; 8];
+        while sent.len() < 3 {
+            let size = read(pty.master, &mut buffer).map_err(std::io::Error::from)?;
+            sent.extend_from_slice(&buffer[..size]);
+        }
+        assert_eq!(
+            sent,
+            [
+                ControlCode::KeyboardOff as u8,
+                0x61,
+                ControlCode::KeyboardOn as u8
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,429 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use serde::Deserialize;
+
+use crate::canvas::push_horizontal_move;
+use crate::{ControlCode, Cursor, PrintJob};
+
+/// Horizontal position of a line in the space it is printed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Alignment {
+    Left,
+    Right,
+    Center,
+}
+
+/// How bold text is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bold {
+    /// Switch on the double print mode of the typewriter, which strikes every character twice
+    DoublePrint,
+    /// Strike every character a second time after stepping back over it
+    Overstrike,
+    /// Like Overstrike, but the second strike is shifted right by half a step, which looks heavier
+    OffsetOverstrike,
+}
+
+/// When underlined text is underlined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Underline {
+    /// Step back over every underlined run right after printing it
+    Word,
+    /// Print the whole line first, then step back once and underline all runs of the line
+    Line,
+}
+
+/// Vertical position of text relative to the line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Script {
+    /// Raised by half a line
+    Superscript,
+    /// Lowered by half a line
+    Subscript,
+}
+
+impl Script {
+    /// Control codes that move the paper before and after printing
+    fn shifts(self) -> (ControlCode, ControlCode) {
+        match self {
+            Script::Superscript => (ControlCode::HalfstepUp, ControlCode::HalfstepDown),
+            Script::Subscript => (ControlCode::HalfstepDown, ControlCode::HalfstepUp),
+        }
+    }
+}
+
+/// Superscript and subscript characters that are not on the daisy wheel,
+/// with the character that is printed raised or lowered in their place
+const SCRIPT_CHARACTERS: [(char, Script, char); 30] = [
+    ('⁰', Script::Superscript, '0'),
+    ('¹', Script::Superscript, '1'),
+    ('⁴', Script::Superscript, '4'),
+    ('⁵', Script::Superscript, '5'),
+    ('⁶', Script::Superscript, '6'),
+    ('⁷', Script::Superscript, '7'),
+    ('⁸', Script::Superscript, '8'),
+    ('⁹', Script::Superscript, '9'),
+    ('⁺', Script::Superscript, '+'),
+    ('⁻', Script::Superscript, '-'),
+    ('⁼', Script::Superscript, '='),
+    ('⁽', Script::Superscript, '('),
+    ('⁾', Script::Superscript, ')'),
+    ('ⁱ', Script::Superscript, 'i'),
+    ('ⁿ', Script::Superscript, 'n'),
+    ('₀', Script::Subscript, '0'),
+    ('₁', Script::Subscript, '1'),
+    ('₂', Script::Subscript, '2'),
+    ('₃', Script::Subscript, '3'),
+    ('₄', Script::Subscript, '4'),
+    ('₅', Script::Subscript, '5'),
+    ('₆', Script::Subscript, '6'),
+    ('₇', Script::Subscript, '7'),
+    ('₈', Script::Subscript, '8'),
+    ('₉', Script::Subscript, '9'),
+    ('₊', Script::Subscript, '+'),
+    ('₋', Script::Subscript, '-'),
+    ('₌', Script::Subscript, '='),
+    ('₍', Script::Subscript, '('),
+    ('₎', Script::Subscript, ')'),
+];
+
+/// Encode a superscript or subscript character that the typewriter can only print by moving the paper
+pub(crate) fn encode_script_character(c: char) -> Option<Vec<u8>> {
+    let (_, script, base) = SCRIPT_CHARACTERS
+        .iter()
+        .find(|(character, _, _)| *character == c)?;
+    let (before, after) = script.shifts();
+
+    let mut encoded = vec![before as u8];
+    encoded.extend_from_slice(gdrascii_codec::encode_char(*base)?);
+    encoded.push(after as u8);
+    Some(encoded)
+}
+
+/// Formatting applied to a run of text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub bold: Option<Bold>,
+    pub underline: Option<Underline>,
+    pub script: Option<Script>,
+}
+
+impl Style {
+    pub fn bold(mode: Bold) -> Style {
+        Style {
+            bold: Some(mode),
+            ..Style::default()
+        }
+    }
+
+    pub fn underline(mode: Underline) -> Style {
+        Style {
+            underline: Some(mode),
+            ..Style::default()
+        }
+    }
+
+    pub fn script(script: Script) -> Style {
+        Style {
+            script: Some(script),
+            ..Style::default()
+        }
+    }
+}
+
+/// Text with a style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+impl Span {
+    pub fn new(text: &str, style: Style) -> Span {
+        Span {
+            text: text.to_owned(),
+            style,
+        }
+    }
+}
+
+/// Number of characters the spans take up on a line
+pub fn spans_width(spans: &[Span]) -> usize {
+    spans.iter().map(|span| span.text.chars().count()).sum()
+}
+
+/// Break styled text into lines of at most width characters, like layout::wrap.
+/// Spaces keep their style, so underlining continues between underlined words.
+pub fn wrap_spans(spans: &[Span], width: usize) -> Vec<Vec<Span>> {
+    let width = width.max(1);
+    let characters: Vec<(char, Style)> = spans
+        .iter()
+        .flat_map(|span| span.text.chars().map(move |c| (c, span.style)))
+        .collect();
+
+    if characters.is_empty() {
+        return Vec::new();
+    }
+
+    let mut lines = Vec::new();
+    for paragraph in characters.split(|(c, _)| *c == '\n') {
+        let mut line: Vec<(char, Style)> = Vec::new();
+
+        for part in paragraph.split_inclusive(|(c, _)| *c == ' ') {
+            let (mut word, separator) = match part.split_last() {
+                Some((last, word)) if last.0 == ' ' => (word.to_vec(), Some(*last)),
+                _ => (part.to_vec(), None),
+            };
+
+            if !line.is_empty() && line.len() + word.len() > width {
+                lines.push(group_spans(&line));
+                line.clear();
+            }
+
+            while word.len() > width - line.len() {
+                let rest = word.split_off(width - line.len());
+                line.extend(word);
+                lines.push(group_spans(&line));
+                line.clear();
+                word = rest;
+            }
+
+            line.extend(word);
+            if let Some(separator) = separator {
+                if line.len() < width {
+                    line.push(separator);
+                }
+            }
+        }
+
+        lines.push(group_spans(&line));
+    }
+
+    lines
+}
+
+/// Join neighbouring characters with the same style into spans, dropping trailing spaces
+fn group_spans(characters: &[(char, Style)]) -> Vec<Span> {
+    let end = characters
+        .iter()
+        .rposition(|(c, _)| *c != ' ')
+        .map_or(0, |index| index + 1);
+
+    let mut spans: Vec<Span> = Vec::new();
+    for (c, style) in &characters[..end] {
+        match spans.last_mut() {
+            Some(span) if span.style == *style => span.text.push(*c),
+            _ => spans.push(Span {
+                text: c.to_string(),
+                style: *style,
+            }),
+        }
+    }
+
+    spans
+}
+
+/// Append styled text.
+/// Line breaks and tabs are handled as in push_text, they are never struck twice or underlined.
+pub(crate) fn push_spans(job: &mut PrintJob, spans: &[Span]) {
+    // Follows the carriage relative to the start of the current line
+    let mut cursor = Cursor::new();
+    // Runs that are underlined once the line is complete, in half characters
+    let mut pending = Vec::new();
+
+    for span in spans {
+        for (index, segment) in span.text.split('\n').enumerate() {
+            if index > 0 {
+                // The carriage is returned anyway, so it is left where the underscores end
+                push_underlines(job, cursor.position(), &pending);
+                pending.clear();
+                job.push_control(ControlCode::Enter);
+                cursor = Cursor::new();
+            }
+
+            let start = cursor.position();
+            let length = job.data().len();
+            push_run(job, segment, span.style);
+            cursor.feed(&job.data()[length..]);
+
+            match span.style.underline {
+                None => {}
+                Some(Underline::Word) => {
+                    let end =
+                        push_underlines(job, cursor.position(), &[(start, cursor.position())]);
+                    push_horizontal_move(job, end, cursor.position());
+                }
+                Some(Underline::Line) => pending.push((start, cursor.position())),
+            }
+        }
+    }
+
+    // Text that follows continues where the styled text ends
+    let end = push_underlines(job, cursor.position(), &pending);
+    push_horizontal_move(job, end, cursor.position());
+}
+
+/// Step back from the position of the carriage and strike underscores below the runs.
+/// Returns the position the carriage is left at, the end of the last underscore.
+fn push_underlines(job: &mut PrintJob, position: u32, runs: &[(u32, u32)]) -> u32 {
+    let mut position = position;
+
+    for (start, end) in runs {
+        if end <= start {
+            continue;
+        }
+
+        push_horizontal_move(job, position, *start);
+        let length = (end - start).div_ceil(2);
+        job.push_text(&"_".repeat(length as usize));
+        position = start + length * 2;
+    }
+
+    position
+}
+
+/// Append a single line of text with its weight and vertical position
+fn push_run(job: &mut PrintJob, text: &str, style: Style) {
+    if text.is_empty() {
+        return;
+    }
+
+    match style.script.map(Script::shifts) {
+        None => push_weighted(job, text, style.bold),
+        Some((before, after)) => {
+            // Sent as one chunk, so cancelling the job never leaves the paper shifted
+            let mut run = PrintJob::empty();
+            run.push_control(before);
+            push_weighted(&mut run, text, style.bold);
+            run.push_control(after);
+            job.push_encoded(run.data());
+        }
+    }
+}
+
+/// Append a single line of text with its weight
+fn push_weighted(job: &mut PrintJob, text: &str, bold: Option<Bold>) {
+    match bold {
+        None => job.push_text(text),
+        Some(Bold::DoublePrint) => {
+            job.push_control(ControlCode::DoublePrint);
+            job.push_text(text);
+            job.push_control(ControlCode::DoublePrint);
+        }
+        Some(mode) => {
+            let mut buffer = [0; 4];
+            for c in text.chars() {
+                let character = c.encode_utf8(&mut buffer);
+                job.push_text(character);
+
+                if c.is_whitespace() || c.is_control() {
+                    continue;
+                }
+
+                job.push_control(ControlCode::Backstep);
+                if mode == Bold::OffsetOverstrike {
+                    job.push_control(ControlCode::HalfstepRight);
+                    job.push_text(character);
+                    job.push_control(ControlCode::HalfstepLeft);
+                } else {
+                    job.push_text(character);
+                }
+            }
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn bold_overstrike() {
+        use crate::style::{Bold, Style};
+        use crate::PrintJob;
+
+        let mut job = PrintJob::empty();
+        job.push_styled("a b", &Style::bold(Bold::Overstrike));
+        assert_eq!(job.data(), b"\x61\x72\x61\x71\x4E\x72\x4E");
+
+        let mut job = PrintJob::empty();
+        job.push_styled("a", &Style::bold(Bold::OffsetOverstrike));
+        assert_eq!(job.data(), b"\x61\x72\x82\x61\x81");
+
+        let mut job = PrintJob::empty();
+        job.push_styled("a", &Style::bold(Bold::DoublePrint));
+        assert_eq!(job.data(), b"\xA9\x61\xA9");
+    }
+
+    #[test]
+    fn underline_words_and_lines() {
+        use crate::style::{Span, Style, Underline};
+        use crate::PrintJob;
+
+        let mut job = PrintJob::empty();
+        job.push_styled("ab", &Style::underline(Underline::Word));
+        assert_eq!(job.data(), b"\x61\x4E\x72\x72\x01\x01");
+
+        // Both runs are underlined after the line, the plain b is skipped with a space
+        let line = Style::underline(Underline::Line);
+        let mut job = PrintJob::empty();
+        job.push_spans(&[
+            Span::new("a", line),
+            Span::new("b", Style::default()),
+            Span::new("a\n", line),
+        ]);
+        assert_eq!(job.data(), b"\x61\x4E\x61\x72\x72\x72\x01\x71\x01\x77");
+    }
+
+    #[test]
+    fn text_after_underline() {
+        use crate::style::{Span, Style, Underline};
+        use crate::PrintJob;
+
+        // The carriage moves forward past the b again before the next a is printed
+        let mut job = PrintJob::empty();
+        job.push_spans(&[
+            Span::new("a", Style::underline(Underline::Line)),
+            Span::new("b", Style::default()),
+        ]);
+        job.push_text("a");
+        assert_eq!(job.data(), b"\x61\x4E\x72\x72\x01\x71\x61");
+    }
+
+    #[test]
+    fn wrap_styled_text() {
+        use crate::style::{wrap_spans, Span, Style, Underline};
+
+        let underline = Style::underline(Underline::Line);
+        let spans = [
+            Span::new("one ", Style::default()),
+            Span::new("two three", underline),
+        ];
+
+        assert_eq!(
+            wrap_spans(&spans, 7),
+            vec![
+                vec![
+                    Span::new("one ", Style::default()),
+                    Span::new("two", underline)
+                ],
+                vec![Span::new("three", underline)],
+            ]
+        );
+    }
+
+    #[test]
+    fn superscript_and_subscript() {
+        use crate::style::{Script, Style};
+        use crate::PrintJob;
+
+        let mut job = PrintJob::empty();
+        job.push_styled("n", &Style::script(Script::Superscript));
+        job.push_text("H₂ 10¹ 10²");
+        assert_eq!(
+            job.data(),
+            b"\x76\x5C\x75\x12\x75\x10\x76\x71\x11\x0D\x76\x11\x75\x71\x11\x0D\x15"
+        );
+        assert!(crate::unprintable_characters("H₂O ¹⁰").is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use crate::{ControlCode, PrintJob};
+
+/// Rows of cells that are printed in aligned columns, using the tab stops of the typewriter.
+#[derive(Debug, Clone)]
+pub struct Table {
+    rows: Vec<Vec<String>>,
+    /// Print a line below the first row
+    pub header: bool,
+    /// Align columns that only contain numbers to the right
+    pub right_align_numbers: bool,
+    /// Empty characters between two columns. Needs to be at least one,
+    /// otherwise Tab would skip a column after a cell that fills it completely.
+    pub column_gap: usize,
+    /// Empty characters in front of every row
+    pub indent: usize,
+}
+
+fn is_number(cell: &str) -> bool {
+    let cell = cell.trim().trim_end_matches('%');
+    !cell.is_empty()
+        && cell.chars().any(|c| c.is_ascii_digit())
+        && cell.chars().enumerate().all(|(i, c)| {
+            c.is_ascii_digit() || matches!(c, '.' | ',') || (i == 0 && matches!(c, '-' | '+'))
+        })
+}
+
+fn width(cell: &str) -> usize {
+    cell.chars().count()
+}
+
+impl Table {
+    pub fn new(rows: Vec<Vec<String>>) -> Table {
+        Table {
+            rows,
+            header: false,
+            right_align_numbers: false,
+            column_gap: 2,
+            indent: 0,
+        }
+    }
+
+    fn column_count(&self) -> usize {
+        self.rows.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    /// Width of the widest cell of each column
+    pub fn column_widths(&self) -> Vec<usize> {
+        let mut widths = vec![0; self.column_count()];
+        for row in &self.rows {
+            for (column, cell) in row.iter().enumerate() {
+                widths[column] = widths[column].max(width(cell));
+            }
+        }
+
+        widths
+    }
+
+    /// Whether a column is aligned to the right
+    fn right_aligned(&self) -> Vec<bool> {
+        let body = if self.header { 1 } else { 0 };
+        (0..self.column_count())
+            .map(|column| {
+                self.right_align_numbers
+                    && self.rows.len() > body
+                    && self.rows[body..]
+                        .iter()
+                        .filter_map(|row| row.get(column))
+                        .all(|cell| cell.trim().is_empty() || is_number(cell))
+            })
+            .collect()
+    }
+
+    /// Create a print job printing the table, starting at the current line.
+    ///
+    /// Tab stops are set at the start of each column while printing the first row,
+    /// all following rows jump between the columns with Tab.
+    /// All tab stops are removed at the end.
+    pub fn render(&self) -> PrintJob {
+        let widths = self.column_widths();
+        let right_aligned = self.right_aligned();
+        let gap = self.column_gap.max(1);
+
+        let mut job = PrintJob::empty();
+        job.push_control(ControlCode::TabAllDel);
+
+        for (index, row) in self.rows.iter().enumerate() {
+            let mut position = 0;
+            job.push_text(&" ".repeat(self.indent));
+
+            for (column, column_width) in widths.iter().enumerate() {
+                let cell = row.get(column).map(|cell| cell.trim()).unwrap_or("");
+
+                if column > 0 {
+                    let start = widths[..column].iter().sum::<usize>() + column * gap;
+                    if index == 0 {
+                        job.push_text(&" ".repeat(start - position));
+                        job.push_control(ControlCode::TabSet);
+                    } else {
+                        job.push_control(ControlCode::Tab);
+                    }
+                    position = start;
+                }
+
+                // The carriage is already at the start of the cell, and an empty one needs no padding
+                if cell.is_empty() {
+                    continue;
+                }
+
+                if right_aligned[column] {
+                    let padding = column_width - width(cell);
+                    job.push_text(&" ".repeat(padding));
+                    position += padding;
+                }
+
+                job.push_text(cell);
+                position += width(cell);
+            }
+
+            job.push_control(ControlCode::Enter);
+
+            if index == 0 && self.header {
+                let rule_width =
+                    widths.iter().sum::<usize>() + widths.len().saturating_sub(1) * gap;
+                job.push_text(&" ".repeat(self.indent));
+                job.push_text(&"-".repeat(rule_width));
+                job.push_control(ControlCode::Enter);
+            }
+        }
+
+        job.push_control(ControlCode::TabAllDel);
+        job
+    }
+}
+
+mod test {
+    #[test]
+    fn right_align_numbers() {
+        use crate::Table;
+
+        let mut table = Table::new(vec![
+            vec!["Item".to_owned(), "Price".to_owned()],
+            vec!["Tea".to_owned(), "3,50".to_owned()],
+            vec!["Cake".to_owned(), "12".to_owned()],
+        ]);
+        table.header = true;
+        table.right_align_numbers = true;
+
+        assert_eq!(table.column_widths(), vec![4, 5]);
+        assert_eq!(table.right_aligned(), vec![false, true]);
+    }
+
+    #[test]
+    fn tab_stops_are_set_in_first_row() {
+        use crate::Table;
+
+        let table = Table::new(vec![
+            vec!["a".to_owned(), "b".to_owned()],
+            vec!["cc".to_owned(), "d".to_owned()],
+        ]);
+
+        // Clear tabs, a, three spaces, set tab, b, Enter, cc, Tab, d, Enter, clear tabs
+        assert_eq!(
+            table.render().data(),
+            b"\x7C\x61\x71\x71\x71\x7A\x4E\x77\x57\x57\x79\x53\x77\x7C"
+        );
+    }
+}
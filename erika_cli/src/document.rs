@@ -0,0 +1,78 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Subcommands that print documents with styled text
+
+use std::fs;
+
+use clap::{Arg, ArgMatches};
+
+use erika_3004::{
+    LineSpacing, Markdown, Markup, PageLayout, PrintJob, Result, TypewriterInterface,
+};
+
+use crate::options;
+
+/// Arguments shared by all document subcommands
+pub fn document_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("FILE").required(true),
+        options::bold_arg(),
+        options::underline_arg(),
+        options::paper_arg(),
+        options::pitch_arg(),
+    ]
+}
+
+/// Default margins of the selected paper. The paper is expected at the left margin of the typewriter.
+fn layout(args: &ArgMatches) -> PageLayout {
+    PageLayout::new(
+        &options::paper(args),
+        options::pitch(args),
+        LineSpacing::Single,
+    )
+}
+
+/// Print a job after switching to the selected pitch
+fn print(
+    interface: &mut TypewriterInterface,
+    args: &ArgMatches,
+    document: &PrintJob,
+) -> Result<()> {
+    let mut job = PrintJob::empty();
+    job.push_control(options::pitch(args).control_code());
+    job.append(document);
+    crate::run_job(interface, &mut job)?;
+    Ok(())
+}
+
+pub fn print_markup(interface: &mut TypewriterInterface, args: &ArgMatches) -> Result<()> {
+    let path = args.value_of("FILE").expect("FILE is required");
+    let text = fs::read_to_string(path)?;
+
+    let layout = layout(args);
+    let markup = Markup {
+        bold: options::bold(args),
+        underline: options::underline(args),
+        margin_left: layout.margin_left,
+        ..Markup::new(layout.text_width())
+    };
+
+    print(interface, args, &markup.render(&text)?)
+}
+
+pub fn print_markdown(interface: &mut TypewriterInterface, args: &ArgMatches) -> Result<()> {
+    let path = args.value_of("FILE").expect("FILE is required");
+    let text = fs::read_to_string(path)?;
+
+    let layout = layout(args);
+    let markdown = Markdown {
+        bold: options::bold(args),
+        underline: options::underline(args),
+        margin_left: layout.margin_left,
+        ..Markdown::new(layout.text_width())
+    };
+
+    print(interface, args, &markdown.render(&text))
+}
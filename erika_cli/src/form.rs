@@ -0,0 +1,43 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use clap::ArgMatches;
+use serde_json::Value;
+
+use erika_3004::{FormTemplate, TypewriterInterface};
+
+/// Read the values to fill in from a JSON object.
+/// Numbers and booleans are printed as they are written in JSON, null leaves the field empty.
+fn read_values(path: &str) -> erika_3004::Result<HashMap<String, String>> {
+    let data: HashMap<String, Value> =
+        serde_json::from_str(&fs::read_to_string(path)?).map_err(io::Error::from)?;
+
+    Ok(data
+        .into_iter()
+        .filter_map(|(name, value)| match value {
+            Value::Null => None,
+            Value::String(text) => Some((name, text)),
+            other => Some((name, other.to_string())),
+        })
+        .collect())
+}
+
+pub fn fill_form(interface: &mut TypewriterInterface, args: &ArgMatches) -> erika_3004::Result<()> {
+    let template_path = args.value_of("TEMPLATE").expect("TEMPLATE is required");
+    let data_path = args.value_of("DATA").expect("DATA is required");
+
+    let template = FormTemplate::from_toml(&fs::read_to_string(template_path)?)?;
+    let values = read_values(data_path)?;
+
+    for name in template.missing_fields(&values) {
+        eprintln!("Warning: No value for field {}, leaving it empty", name);
+    }
+
+    crate::run_job(interface, &mut template.fill(&values)?)?;
+    Ok(())
+}
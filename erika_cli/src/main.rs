@@ -2,23 +2,82 @@
 //
 // SPDX-License-Identifier: EUPL-1.2
 
-use erika_3004::TypewriterInterface;
+use erika_3004::{Checkpoint, JobOutcome, Margins, PageLayout, PrintJob, TypewriterInterface};
 
 use std::fs;
 use std::io;
-use std::io::Read;
+use std::path::PathBuf;
 
 #[cfg(not(target_os = "linux"))]
 use std::process;
 
 use std::time::Duration;
 
-use clap::{Arg, Command};
+use clap::{Arg, ArgMatches, Command};
 
 const SERIAL_DEVICE: &str = "/dev/ttyUSB0";
 
+mod document;
+mod form;
 #[cfg(target_os = "linux")]
 mod keyboard;
+mod merge;
+mod options;
+mod print;
+mod table;
+
+/// Print a job that can be aborted by pressing any key on the typewriter
+fn run_job(
+    interface: &mut TypewriterInterface,
+    job: &mut PrintJob,
+) -> erika_3004::Result<JobOutcome> {
+    job.set_cancel_on_keypress(true);
+
+    let report = job.run(interface)?;
+    if let Some(e) = report.checkpoint_error {
+        eprintln!(
+            "Failed to save the progress, the job can't be resumed: {}",
+            e
+        );
+    }
+
+    let outcome = report.outcome;
+    if let JobOutcome::Cancelled { written } = outcome {
+        eprintln!(
+            "Printing was aborted after {} of {} bytes.",
+            written,
+            job.data().len()
+        );
+    }
+
+    Ok(outcome)
+}
+
+fn estimate_file(args: &ArgMatches) -> erika_3004::Result<()> {
+    let path = args.value_of("FILE").expect("FILE is required");
+    let settings = options::print_settings(args);
+    let estimate = erika_3004::estimate(&fs::read_to_string(path)?, &settings);
+
+    let seconds = estimate.duration.as_secs();
+    println!(
+        "Duration: {}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    println!("Lines:    {}", estimate.lines);
+    println!("Strikes:  {}", estimate.strikes);
+    println!("Paper:    {:.0} mm", estimate.paper_mm);
+    if args.is_present("paper") {
+        let layout = PageLayout::new(&options::paper(args), settings.pitch, settings.line_spacing);
+        println!(
+            "Sheets:   {}",
+            estimate.lines.div_ceil(layout.body_length())
+        );
+    }
+    println!("Ribbon:   {:.1} m", estimate.ribbon_mm / 1000.0);
+    Ok(())
+}
 
 fn main() -> erika_3004::Result<()> {
     let matches = Command::new("erika-cli")
@@ -33,13 +92,28 @@ fn main() -> erika_3004::Result<()> {
         .arg_required_else_help(true)
         .subcommand(
             Command::new("print-file")
-                .about("Print a text file")
-                .arg(Arg::new("NAME").required(true)),
+                .about("Print a text file. Pressing any key on the typewriter aborts printing.")
+                .arg(Arg::new("NAME").required(true))
+                .arg(
+                    Arg::new("reset")
+                        .long("reset")
+                        .help("Reset the typewriter when printing is aborted"),
+                )
+                .arg(
+                    Arg::new("resume")
+                        .long("resume")
+                        .help("Continue an aborted job after the last line that was printed completely"),
+                )
+                .args(options::layout_args()),
         )
         .subcommand(Command::new("keyboard").about(
-            "Connect the typewriter as a keyboard. You need to run erika-cli enable-keyboard afterwards to make the machine print again.",
+            "Connect the typewriter as a keyboard. Direct printing is restored when exiting with Ctrl + C.",
         ))
-        .subcommand(Command::new("print").about("Print text from stdin"))
+        .subcommand(
+            Command::new("print")
+                .about("Print text from stdin")
+                .args(print::print_args()),
+        )
         .subcommand(Command::new("bell").about("Sound the bell"))
         .subcommand(Command::new("enable-keyboard").about("Re-enable direct printing of key presses"))
         .subcommand(
@@ -52,8 +126,118 @@ fn main() -> erika_3004::Result<()> {
                 )
                 .about("Move the paper"),
         )
+        .subcommand(
+            Command::new("estimate")
+                .about("Estimate how long printing a text file takes, and how much paper and ribbon it uses")
+                .arg(Arg::new("FILE").required(true))
+                .args(options::spacing_args())
+                .arg(options::paper_arg())
+                .arg(options::baud_rate_arg()),
+        )
+        .subcommand(
+            Command::new("fill-form")
+                .about("Print values into the fields of a pre-printed form. Align the print head with the top left corner of the form first.")
+                .arg(
+                    Arg::new("TEMPLATE")
+                        .required(true)
+                        .help("TOML file describing the positions of the fields"),
+                )
+                .arg(
+                    Arg::new("DATA")
+                        .required(true)
+                        .help("JSON object with a value for each field"),
+                ),
+        )
+        .subcommand(
+            Command::new("merge")
+                .about("Print a letter for every row of a CSV file, each on its own sheet")
+                .arg(
+                    Arg::new("TEMPLATE")
+                        .required(true)
+                        .help("Text file with placeholders like {{name}} for the columns of the CSV file"),
+                )
+                .arg(
+                    Arg::new("DATA")
+                        .required(true)
+                        .help("CSV file with the column names in the first row"),
+                )
+                .arg(
+                    Arg::new("substitute")
+                        .long("substitute")
+                        .help("Print question marks for characters the typewriter doesn't have"),
+                )
+                .args(options::layout_args()),
+        )
+        .subcommand(
+            Command::new("set-margins")
+                .about("Set the left and right margin. Insert the paper at the left end of the carriage first.")
+                .arg(
+                    Arg::new("left")
+                        .long("left")
+                        .help("Distance from the left edge of the paper in mm")
+                        .default_value("25")
+                        .validator(|value| value.parse::<f64>()),
+                )
+                .arg(
+                    Arg::new("right")
+                        .long("right")
+                        .help("Distance from the right edge of the paper in mm")
+                        .default_value("25")
+                        .validator(|value| value.parse::<f64>()),
+                )
+                .arg(options::paper_arg())
+                .arg(options::pitch_arg()),
+        )
+        .subcommand(
+            Command::new("delete-margin")
+                .about("Delete the margin the carriage is at. Move the carriage to the margin first.")
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .help("Delete the left and the right margin, wherever the carriage is"),
+                ),
+        )
+        .subcommand(
+            Command::new("print-markup")
+                .about("Print a text file with markup: *bold*, _underline_, ^{superscript}, _{subscript}, and {center} or {right} at the start of a line")
+                .args(document::document_args()),
+        )
+        .subcommand(
+            Command::new("print-markdown")
+                .about("Print a Markdown document with bold and underlined headings, lists, quotes and tables")
+                .args(document::document_args()),
+        )
+        .subcommand(
+            Command::new("print-table")
+                .about("Print a CSV or TSV file as a table, using the tab stops of the typewriter")
+                .arg(
+                    Arg::new("FILE")
+                        .required(true)
+                        .help("CSV file, or TSV file if the name ends with .tsv"),
+                )
+                .arg(
+                    Arg::new("tsv")
+                        .long("tsv")
+                        .help("Columns are separated by tabs instead of commas"),
+                )
+                .arg(
+                    Arg::new("header")
+                        .long("header")
+                        .help("Underline the first row with a rule"),
+                )
+                .arg(
+                    Arg::new("right-align-numbers")
+                        .long("right-align-numbers")
+                        .help("Align columns that only contain numbers to the right"),
+                ),
+        )
         .get_matches();
 
+    // Subcommands that don't need the typewriter
+    if let Some(("estimate", estimate_args)) = matches.subcommand() {
+        return estimate_file(estimate_args);
+    }
+
     if let Some(subcommand) = matches.subcommand() {
         let device = matches
             .value_of("device")
@@ -64,37 +248,95 @@ fn main() -> erika_3004::Result<()> {
         match subcommand {
             ("print-file", print_args) => {
                 let path = print_args.value_of("NAME").expect("NAME is required");
-                interface.enable_remote_mode()?;
-                interface.write_unicode(&fs::read_to_string(path)?)?;
-                interface.disable_remote_mode()?;
-            }
-            ("print", _) => {
-                println!("Info: Text typed here will be printed.");
-                println!("Info: Exit by pressing Ctrl + D");
-                interface.enable_remote_mode()?;
+                let checkpoint_path = PathBuf::from(format!("{}.checkpoint", path));
 
-                let mut stdin = io::stdin();
+                let text = fs::read_to_string(path)?;
+                let mut job = if options::paginate(print_args) {
+                    options::page_layout(print_args).render(&text)
+                } else {
+                    let mut job = PrintJob::empty();
+                    job.push_control(options::pitch(print_args).control_code());
+                    job.push_control(options::line_spacing(print_args).control_code());
+                    job.mark_prologue();
+                    job.push_text(&text);
+                    job
+                };
+                job.set_reset_on_cancel(print_args.is_present("reset"));
+                job.set_checkpoint_file(&checkpoint_path);
 
-                loop {
-                    let mut buffer = Vec::<u8>::new();
-                    buffer.resize(20, 0);
-
-                    let size = stdin.read(&mut buffer)?;
-                    if size == 0 {
-                        break;
+                if print_args.is_present("resume") {
+                    if !checkpoint_path.exists() {
+                        eprintln!("There is no aborted job to resume for {}", path);
+                        return Ok(());
                     }
 
-                    let decoded = String::from_utf8(buffer[0..size].to_vec()).unwrap();
+                    job.resume(&Checkpoint::load(&checkpoint_path)?)?;
 
-                    interface.write_unicode(&decoded)?;
+                    if options::paginate(print_args) && job.start() > 0 {
+                        println!("Insert a fresh sheet of paper when the typewriter rings");
+                    } else {
+                        println!("Insert a fresh sheet of paper and press Enter to continue");
+                        io::stdin().read_line(&mut String::new())?;
+                    }
                 }
 
-                interface.disable_remote_mode()?;
+                if let JobOutcome::Cancelled { .. } = run_job(&mut interface, &mut job)? {
+                    eprintln!("Continue with --resume.");
+                }
+            }
+            ("fill-form", form_args) => {
+                form::fill_form(&mut interface, form_args)?;
+            }
+            ("merge", merge_args) => {
+                merge::merge(&mut interface, merge_args)?;
+            }
+            ("set-margins", margin_args) => {
+                let millimetres = |name| {
+                    margin_args
+                        .value_of(name)
+                        .expect("margins have default values")
+                        .parse()
+                        .expect("validated by clap")
+                };
+
+                let margins = Margins::from_mm(
+                    &options::paper(margin_args),
+                    options::pitch(margin_args),
+                    millimetres("left"),
+                    millimetres("right"),
+                )?;
+                let mut interface = interface.remote_mode()?;
+                interface.set_margins(&margins)?;
+                interface.finish()?;
+                println!(
+                    "Margins set at columns {} and {}, {} characters per line",
+                    margins.left,
+                    margins.right,
+                    margins.width()
+                );
+            }
+            ("delete-margin", delete_args) => {
+                let mut interface = interface.remote_mode()?;
+                interface.delete_margin(delete_args.is_present("all"))?;
+                interface.finish()?;
+            }
+            ("print-markup", markup_args) => {
+                document::print_markup(&mut interface, markup_args)?;
+            }
+            ("print-markdown", markdown_args) => {
+                document::print_markdown(&mut interface, markdown_args)?;
+            }
+            ("print-table", table_args) => {
+                table::print_table(&mut interface, table_args)?;
+            }
+            ("print", print_args) => {
+                print::print_stdin(&mut interface, print_args)?;
             }
             #[cfg(target_os = "linux")]
             ("keyboard", _) => {
-                interface.enable_remote_mode()?;
+                let mut interface = interface.remote_mode()?;
                 keyboard::watch_keyboard_input(&mut interface)?;
+                interface.finish()?;
             }
             #[cfg(not(target_os = "linux"))]
             ("keyboard", _) => {
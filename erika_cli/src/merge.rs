@@ -0,0 +1,72 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use clap::ArgMatches;
+
+use erika_3004::merge::fill_placeholders;
+use erika_3004::{ErikaError, PrintJob, TypewriterInterface};
+
+use crate::options;
+
+/// Print the template once for every row of the CSV file, each on its own sheet
+pub fn merge(interface: &mut TypewriterInterface, args: &ArgMatches) -> erika_3004::Result<()> {
+    let template_path = args.value_of("TEMPLATE").expect("TEMPLATE is required");
+    let data_path = args.value_of("DATA").expect("DATA is required");
+
+    let template = fs::read_to_string(template_path)?;
+    let mut reader = csv::Reader::from_path(data_path).map_err(io::Error::from)?;
+    let headers = reader.headers().map_err(io::Error::from)?.clone();
+
+    let mut job = PrintJob::empty();
+    let mut unprintable_characters: Vec<char> = Vec::new();
+
+    for (index, record) in reader.records().enumerate() {
+        let record = record.map_err(io::Error::from)?;
+        let values: HashMap<String, String> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+
+        let letter = fill_placeholders(&template, &values)?;
+
+        // Row 1 is the header
+        let row = index + 2;
+        let unprintable = erika_3004::unprintable_characters(&letter);
+        if !unprintable.is_empty() {
+            eprintln!(
+                "Row {} contains characters that can't be printed: {}",
+                row,
+                unprintable.iter().collect::<String>()
+            );
+            for c in unprintable {
+                if !unprintable_characters.contains(&c) {
+                    unprintable_characters.push(c);
+                }
+            }
+        }
+
+        if index > 0 {
+            job.push_page_break();
+        }
+
+        if options::paginate(args) {
+            job.append(&options::page_layout(args).render(&letter));
+        } else {
+            job.push_text(&letter);
+        }
+    }
+
+    if !unprintable_characters.is_empty() && !args.is_present("substitute") {
+        eprintln!("Nothing was printed. Pass --substitute to print question marks instead.");
+        return Err(ErikaError::UnprintableCharacters(unprintable_characters));
+    }
+
+    crate::run_job(interface, &mut job)?;
+    Ok(())
+}
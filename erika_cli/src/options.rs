@@ -0,0 +1,218 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Command line arguments shared by several subcommands
+
+use clap::{Arg, ArgMatches};
+
+use erika_3004::style::{Bold, Underline};
+use erika_3004::{BoudRate, LineSpacing, PageLayout, PaperSize, Pitch, PrintSettings};
+
+pub fn pitch_arg() -> Arg<'static> {
+    Arg::new("pitch")
+        .long("pitch")
+        .help("Characters per inch")
+        .possible_values(Pitch::NAMES)
+        .default_value("10")
+}
+
+/// Arguments that select the character and line spacing
+pub fn spacing_args() -> [Arg<'static>; 2] {
+    [
+        pitch_arg(),
+        Arg::new("line-spacing")
+            .long("line-spacing")
+            .help("Line spacing")
+            .possible_values(LineSpacing::NAMES)
+            .default_value("1"),
+    ]
+}
+
+pub fn baud_rate_arg() -> Arg<'static> {
+    Arg::new("baud-rate")
+        .long("baud-rate")
+        .help("Baud rate of the serial connection")
+        .possible_values(["1200", "2400", "4800", "9600", "19200"])
+        .default_value("1200")
+}
+
+pub fn paper_arg() -> Arg<'static> {
+    Arg::new("paper")
+        .long("paper")
+        .takes_value(true)
+        .help("Paper size, used to compute the number of lines and characters that fit on a sheet")
+        .possible_values(PaperSize::names())
+        .ignore_case(true)
+}
+
+/// How bold text is printed. The typewriter's double print mode is used by default.
+pub fn bold_arg() -> Arg<'static> {
+    Arg::new("bold")
+        .long("bold")
+        .takes_value(true)
+        .help("How to print bold text: with the double print mode of the typewriter, or by striking characters twice")
+        .possible_values(["double-print", "overstrike", "offset-overstrike"])
+}
+
+pub fn bold(args: &ArgMatches) -> Bold {
+    match args.value_of("bold") {
+        Some("overstrike") => Bold::Overstrike,
+        Some("offset-overstrike") => Bold::OffsetOverstrike,
+        _ => Bold::DoublePrint,
+    }
+}
+
+pub fn underline_arg() -> Arg<'static> {
+    Arg::new("underline")
+        .long("underline")
+        .takes_value(true)
+        .help("When to underline text: after every word, or after every line to save carriage movements")
+        .possible_values(["word", "line"])
+}
+
+pub fn underline(args: &ArgMatches) -> Underline {
+    match args.value_of("underline") {
+        Some("word") => Underline::Word,
+        _ => Underline::Line,
+    }
+}
+
+pub fn pitch(args: &ArgMatches) -> Pitch {
+    args.value_of("pitch")
+        .and_then(Pitch::from_name)
+        .unwrap_or(Pitch::Chars10PerInch)
+}
+
+pub fn line_spacing(args: &ArgMatches) -> LineSpacing {
+    args.value_of("line-spacing")
+        .and_then(LineSpacing::from_name)
+        .unwrap_or(LineSpacing::Single)
+}
+
+pub fn baud_rate(args: &ArgMatches) -> BoudRate {
+    match args.value_of("baud-rate") {
+        Some("2400") => BoudRate::Rate2400,
+        Some("4800") => BoudRate::Rate4800,
+        Some("9600") => BoudRate::Rate9600,
+        Some("19200") => BoudRate::Rate19200,
+        _ => BoudRate::Rate1200,
+    }
+}
+
+/// Selected paper size, A4 if none was passed
+pub fn paper(args: &ArgMatches) -> PaperSize {
+    args.value_of("paper")
+        .and_then(PaperSize::from_name)
+        .unwrap_or(PaperSize::A4)
+}
+
+/// Settings for subcommands that take spacing_args and baud_rate_arg
+pub fn print_settings(args: &ArgMatches) -> PrintSettings {
+    PrintSettings {
+        baud_rate: baud_rate(args),
+        pitch: pitch(args),
+        line_spacing: line_spacing(args),
+    }
+}
+
+/// Layout options that take a number, with the field of PageLayout they set
+#[allow(clippy::type_complexity)]
+const LAYOUT_SIZES: [(&str, &str, fn(&mut PageLayout) -> &mut usize); 6] = [
+    ("page-length", "Number of lines on a sheet", |l| {
+        &mut l.page_length
+    }),
+    ("page-width", "Number of characters on a line", |l| {
+        &mut l.page_width
+    }),
+    ("margin-top", "Empty lines at the top of every sheet", |l| {
+        &mut l.margin_top
+    }),
+    (
+        "margin-bottom",
+        "Empty lines at the bottom of every sheet",
+        |l| &mut l.margin_bottom,
+    ),
+    (
+        "margin-left",
+        "Empty characters at the start of every line",
+        |l| &mut l.margin_left,
+    ),
+    (
+        "margin-right",
+        "Empty characters at the end of every line",
+        |l| &mut l.margin_right,
+    ),
+];
+
+/// Arguments that control pagination. Also includes paper_arg and spacing_args.
+pub fn layout_args() -> Vec<Arg<'static>> {
+    let mut args = vec![
+        Arg::new("paginate")
+            .long("paginate")
+            .help("Split the text into pages, and wait for a key press after each sheet"),
+        Arg::new("header")
+            .long("header")
+            .takes_value(true)
+            .help("Text at the top of every page, {page} is replaced by the page number"),
+        Arg::new("footer")
+            .long("footer")
+            .takes_value(true)
+            .help("Text at the bottom of every page, {page} is replaced by the page number"),
+        Arg::new("page-numbers")
+            .long("page-numbers")
+            .help("Number the pages"),
+        Arg::new("hardware-margins").long("hardware-margins").help(
+            "Program the left and right margin into the typewriter instead of indenting lines",
+        ),
+        paper_arg(),
+    ];
+
+    args.extend(spacing_args());
+
+    for (name, help, _) in LAYOUT_SIZES {
+        args.push(
+            Arg::new(name)
+                .long(name)
+                .takes_value(true)
+                .help(help)
+                .validator(|value| value.parse::<usize>()),
+        );
+    }
+
+    args
+}
+
+/// Page layout for the selected paper, with all sizes that were passed overridden
+pub fn page_layout(args: &ArgMatches) -> PageLayout {
+    let mut layout = PageLayout {
+        header: args.value_of("header").map(str::to_owned),
+        footer: args.value_of("footer").map(str::to_owned),
+        page_numbers: args.is_present("page-numbers"),
+        hardware_margins: args.is_present("hardware-margins"),
+        ..PageLayout::new(&paper(args), pitch(args), line_spacing(args))
+    };
+
+    for (name, _, field) in LAYOUT_SIZES {
+        if let Some(value) = args.value_of(name) {
+            *field(&mut layout) = value.parse().expect("validated by clap");
+        }
+    }
+
+    layout
+}
+
+/// Whether any argument was passed that only makes sense when splitting the text into pages
+pub fn paginate(args: &ArgMatches) -> bool {
+    [
+        "paginate",
+        "header",
+        "footer",
+        "page-numbers",
+        "hardware-margins",
+        "paper",
+    ]
+    .into_iter()
+    .chain(LAYOUT_SIZES.map(|(name, _, _)| name))
+    .any(|name| args.is_present(name))
+}
@@ -0,0 +1,111 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use std::io;
+use std::io::{BufRead, Read};
+
+use clap::{Arg, ArgMatches};
+
+use erika_3004::{overstrike, Ansi, Escp, Style, TypewriterInterface};
+
+use crate::options;
+
+pub fn print_args() -> Vec<Arg<'static>> {
+    vec![
+        options::bold_arg().help(
+            "Print all text bold using the given method, or with --overstrike, --ansi or --escp, how bold text is printed",
+        ),
+        options::underline_arg().help(
+            "Underline all text after every word or every line, or with --overstrike, --ansi or --escp, how underlined text is printed",
+        ),
+        Arg::new("overstrike")
+            .long("overstrike")
+            .help("Interpret backspace sequences for bold and underlined text, as in the output of man and nroff"),
+        Arg::new("ansi")
+            .long("ansi")
+            .conflicts_with("overstrike")
+            .help("Interpret bold, underline and cursor movements of terminal escape sequences, and remove all others"),
+        Arg::new("escp")
+            .long("escp")
+            .conflicts_with_all(&["overstrike", "ansi"])
+            .help("Interpret the input as ESC/P printer commands, and print it once it is complete"),
+    ]
+}
+
+/// Print text from stdin as it is typed
+pub fn print_stdin(
+    interface: &mut TypewriterInterface,
+    args: &ArgMatches,
+) -> erika_3004::Result<()> {
+    if args.is_present("escp") {
+        // Form feeds need to wait for paper, so this is printed as a job
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+
+        let mut escp = Escp::new(options::bold(args), options::underline(args));
+        crate::run_job(interface, &mut escp.feed(&data))?;
+        return Ok(());
+    }
+
+    println!("Info: Text typed here will be printed.");
+    println!("Info: Exit by pressing Ctrl + D");
+    let mut interface = interface.remote_mode()?;
+
+    if args.is_present("overstrike") || args.is_present("ansi") {
+        // Filters are applied line by line, so a line is never split inside a sequence
+        let bold = options::bold(args);
+        let underline = options::underline(args);
+        let mut ansi = Ansi::new(bold, underline);
+
+        for line in io::stdin().lock().split(b'\n') {
+            let mut line = String::from_utf8_lossy(&line?).into_owned();
+            line.push('\n');
+
+            let spans = if args.is_present("ansi") {
+                ansi.feed(&line)
+            } else {
+                overstrike::parse(&line, bold, underline)
+            };
+            interface.write_spans(&spans)?;
+        }
+
+        return interface.finish();
+    }
+
+    let mut stdin = io::stdin();
+    let style = Style {
+        bold: args.is_present("bold").then(|| options::bold(args)),
+        underline: args
+            .is_present("underline")
+            .then(|| options::underline(args)),
+        ..Style::default()
+    };
+
+    let mut pending = Vec::new();
+    loop {
+        let mut buffer = vec![0; 20];
+
+        let size = stdin.read(&mut buffer)?;
+        if size == 0 {
+            break;
+        }
+        pending.extend_from_slice(&buffer[0..size]);
+
+        // A character can be split between two reads, so an incomplete one is kept for the next
+        let complete = match std::str::from_utf8(&pending) {
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            _ => pending.len(),
+        };
+        let decoded = String::from_utf8_lossy(&pending[..complete]).into_owned();
+        pending.drain(..complete);
+
+        interface.write_styled(&decoded, &style)?;
+    }
+
+    if !pending.is_empty() {
+        interface.write_styled(&String::from_utf8_lossy(&pending), &style)?;
+    }
+
+    interface.finish()
+}
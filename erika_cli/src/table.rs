@@ -0,0 +1,53 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+use std::io;
+use std::path::Path;
+
+use clap::ArgMatches;
+
+use erika_3004::{Table, TypewriterInterface};
+
+/// Print a CSV or TSV file as a table with aligned columns
+pub fn print_table(
+    interface: &mut TypewriterInterface,
+    args: &ArgMatches,
+) -> erika_3004::Result<()> {
+    let path = args.value_of("FILE").expect("FILE is required");
+
+    let is_tsv = Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("tsv"));
+    let delimiter = if args.is_present("tsv") || is_tsv {
+        b'\t'
+    } else {
+        b','
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)
+        .map_err(io::Error::from)?;
+
+    let rows = reader
+        .records()
+        .map(|record| {
+            record
+                .map(|record| record.iter().map(str::to_owned).collect())
+                .map_err(io::Error::from)
+        })
+        .collect::<io::Result<Vec<Vec<String>>>>()?;
+
+    let mut table = Table::new(rows);
+    table.header = args.is_present("header");
+    table.right_align_numbers = args.is_present("right-align-numbers");
+
+    let mut job = table.render();
+    // Don't leave the tab stops of an aborted table behind
+    job.set_reset_on_cancel(true);
+    crate::run_job(interface, &mut job)?;
+    Ok(())
+}
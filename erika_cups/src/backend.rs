@@ -0,0 +1,126 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! CUPS backend for device URIs like erika:/dev/ttyUSB0.
+//!
+//! The text is laid out on the paper size of the job, and printing stops after every sheet
+//! until a key is pressed on the typewriter. Cancelling the job in CUPS or pressing a key
+//! while printing aborts the job within a few characters, with the keyboard re-enabled.
+
+use std::env;
+use std::fs;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use erika_3004::{JobOutcome, ProgressHandle, TypewriterInterface};
+use erika_cups::{Job, CUPS_BACKEND_FAILED, CUPS_BACKEND_OK};
+
+const SCHEME: &str = "erika:";
+
+/// How often the state of the job is checked for reporting to CUPS
+const STATE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Print a line for every serial adapter, for discovery by CUPS
+fn list_devices() {
+    let mut devices: Vec<String> = fs::read_dir("/dev")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("ttyUSB") || name.starts_with("ttyACM"))
+        .map(|name| format!("/dev/{}", name))
+        .collect();
+    devices.sort();
+
+    // Lets the URI be entered manually when no adapter is connected
+    if devices.is_empty() {
+        println!("serial erika \"Unknown\" \"Robotron Erika S3004\"");
+    }
+    for device in devices {
+        println!(
+            "serial {}{} \"Robotron Erika S3004\" \"Erika S3004 on {}\"",
+            SCHEME, device, device
+        );
+    }
+}
+
+/// Path of the serial device in erika:/dev/ttyUSB0 or erika:///dev/ttyUSB0
+fn device_path(uri: &str) -> Option<String> {
+    let path = uri.strip_prefix(SCHEME)?.trim_start_matches('/');
+    if path.is_empty() {
+        None
+    } else {
+        Some(format!("/{}", path))
+    }
+}
+
+/// Tell CUPS when a new sheet needs to be inserted
+fn report_paper_state(progress: ProgressHandle) {
+    thread::spawn(move || {
+        let mut waiting = false;
+        loop {
+            if progress.is_waiting_for_paper() != waiting {
+                waiting = !waiting;
+                if waiting {
+                    eprintln!("STATE: +media-needed");
+                    eprintln!("INFO: Insert a new sheet and press any key on the typewriter");
+                } else {
+                    eprintln!("STATE: -media-needed");
+                }
+            }
+
+            thread::sleep(STATE_POLL_INTERVAL);
+        }
+    });
+}
+
+fn print(job: &Job, device: &str) -> erika_3004::Result<JobOutcome> {
+    let text = String::from_utf8_lossy(&job.read()?).into_owned();
+
+    let mut print_job = job.page_layout().render(&text);
+    print_job.set_cancel_on_keypress(true);
+    print_job.set_cancel_on_signal(true);
+
+    let mut interface = TypewriterInterface::new(device)?;
+    report_paper_state(print_job.progress_handle());
+    Ok(print_job.run(&mut interface)?.outcome)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() == 1 {
+        list_devices();
+        process::exit(CUPS_BACKEND_OK);
+    }
+
+    let job = match Job::from_args(&args[1..]) {
+        Some(job) => job,
+        None => {
+            eprintln!("Usage: erika job-id user title copies options [file]");
+            process::exit(CUPS_BACKEND_FAILED);
+        }
+    };
+
+    let uri = env::var("DEVICE_URI").unwrap_or_else(|_| args[0].clone());
+    let device = match device_path(&uri) {
+        Some(device) => device,
+        None => {
+            eprintln!("ERROR: {} does not name a serial device", uri);
+            process::exit(CUPS_BACKEND_FAILED);
+        }
+    };
+
+    match print(&job, &device) {
+        Ok(JobOutcome::Completed) => process::exit(CUPS_BACKEND_OK),
+        Ok(JobOutcome::Cancelled { .. }) => {
+            eprintln!("INFO: Printing of {} was aborted", job.title);
+            process::exit(CUPS_BACKEND_OK);
+        }
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            process::exit(CUPS_BACKEND_FAILED);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! CUPS filter that converts text in any character set to UTF-8 the typewriter can print.
+//! Layout and pagination are left to the backend, which knows when a new sheet is needed.
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use erika_3004::Encoding;
+use erika_cups::{Job, CUPS_BACKEND_FAILED};
+
+fn filter(job: &Job) -> erika_3004::Result<()> {
+    let data = job.read()?;
+
+    // CUPS passes the character set of text documents in the environment
+    let encoding = env::var("CHARSET")
+        .ok()
+        .and_then(|charset| Encoding::from_name(&charset))
+        .unwrap_or_else(|| Encoding::detect(&data, Encoding::Windows1252));
+    let text = job.substitution().apply(&encoding.decode(&data))?;
+
+    // The typewriter can't make copies by itself, each one starts on a new sheet
+    let copies = vec![text; job.copies.max(1)].join("\x0c");
+    io::stdout().write_all(copies.as_bytes())?;
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let job = match Job::from_args(&args) {
+        Some(job) => job,
+        None => {
+            eprintln!("Usage: texttoerika job-id user title copies options [file]");
+            process::exit(CUPS_BACKEND_FAILED);
+        }
+    };
+
+    if let Err(e) = filter(&job) {
+        eprintln!("ERROR: {}", e);
+        process::exit(CUPS_BACKEND_FAILED);
+    }
+}
@@ -0,0 +1,158 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Parts shared by the CUPS backend and the text filter
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use erika_3004::paper::STEPS_PER_INCH;
+use erika_3004::{LineSpacing, PageLayout, PaperSize, Pitch, Substitution};
+
+/// The job was printed, or cancelled on purpose
+pub const CUPS_BACKEND_OK: i32 = 0;
+/// The job could not be printed
+pub const CUPS_BACKEND_FAILED: i32 = 1;
+
+/// Sizes in job options are measured in points
+const POINTS_PER_INCH: u32 = 72;
+
+/// Job as it is passed to filters and backends on the command line
+#[derive(Debug)]
+pub struct Job {
+    pub id: String,
+    pub user: String,
+    pub title: String,
+    pub copies: usize,
+    pub options: HashMap<String, String>,
+    /// File to print. The document is read from stdin if there is none.
+    pub file: Option<PathBuf>,
+}
+
+/// Split the options string of a job into names and values.
+/// Options without a value are set to true.
+pub fn parse_options(options: &str) -> HashMap<String, String> {
+    options
+        .split_whitespace()
+        .map(|option| match option.split_once('=') {
+            Some((name, value)) => (name.to_owned(), value.trim_matches('"').to_owned()),
+            None => (option.to_owned(), "true".to_owned()),
+        })
+        .collect()
+}
+
+impl Job {
+    /// Read the arguments that follow the program name: job id, user, title, copies, options and the optional file
+    pub fn from_args(args: &[String]) -> Option<Job> {
+        if !(5..=6).contains(&args.len()) {
+            return None;
+        }
+
+        Some(Job {
+            id: args[0].clone(),
+            user: args[1].clone(),
+            title: args[2].clone(),
+            copies: args[3].parse().ok()?,
+            options: parse_options(&args[4]),
+            file: args.get(5).map(PathBuf::from),
+        })
+    }
+
+    /// Contents of the document
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        match &self.file {
+            Some(path) => fs::read(path),
+            None => {
+                let mut data = Vec::new();
+                io::stdin().read_to_end(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    /// Layout selected by the paper size, cpi, LineSpacing and page-left, -right, -top and -bottom options
+    pub fn page_layout(&self) -> PageLayout {
+        // PWG media names like iso_a4_210x297mm contain the name of the size
+        let paper = ["PageSize", "media"]
+            .into_iter()
+            .filter_map(|name| self.option(name))
+            .flat_map(|value| value.split(['_', ',']))
+            .find_map(PaperSize::from_name)
+            .unwrap_or(PaperSize::A4);
+        let pitch = self
+            .option("cpi")
+            .and_then(Pitch::from_name)
+            .unwrap_or(Pitch::Chars10PerInch);
+        let line_spacing = match self.option("LineSpacing") {
+            Some("OneAndAHalf") => LineSpacing::OneAndAHalf,
+            Some("Double") => LineSpacing::Double,
+            _ => LineSpacing::Single,
+        };
+
+        let mut layout = PageLayout::new(&paper, pitch, line_spacing);
+        let points = |name| {
+            self.option(name)
+                .and_then(|value| value.parse::<u32>().ok())
+        };
+        let columns = |points: u32| (points * pitch.chars_per_inch() / POINTS_PER_INCH) as usize;
+        let lines = |points: u32| {
+            (points * STEPS_PER_INCH / POINTS_PER_INCH / line_spacing.paper_steps()) as usize
+        };
+
+        if let Some(left) = points("page-left") {
+            layout.margin_left = columns(left);
+        }
+        if let Some(right) = points("page-right") {
+            layout.margin_right = columns(right);
+        }
+        if let Some(top) = points("page-top") {
+            layout.margin_top = lines(top);
+        }
+        if let Some(bottom) = points("page-bottom") {
+            layout.margin_bottom = lines(bottom);
+        }
+
+        layout
+    }
+
+    /// What happens to characters the typewriter can't print, question marks by default
+    pub fn substitution(&self) -> Substitution {
+        self.option("Substitution")
+            .and_then(Substitution::from_name)
+            .unwrap_or(Substitution::QuestionMark)
+    }
+}
+
+mod test {
+    #[test]
+    fn layout_from_job_options() {
+        use crate::Job;
+        use erika_3004::{Pitch, Substitution};
+
+        let args = [
+            "12",
+            "jbb",
+            "letter.txt",
+            "2",
+            "media=iso_a5_148x210mm cpi=12 page-left=36 page-top=72 Substitution=transliterate",
+        ]
+        .map(str::to_owned);
+        let job = Job::from_args(&args).unwrap();
+
+        let layout = job.page_layout();
+        assert_eq!(layout.pitch, Pitch::Chars12PerInch);
+        assert_eq!(layout.page_width, 69);
+        assert_eq!(layout.margin_left, 6);
+        assert_eq!(layout.margin_top, 6);
+        assert_eq!(job.copies, 2);
+        assert_eq!(job.substitution(), Substitution::Transliterate);
+        assert!(job.file.is_none());
+    }
+}
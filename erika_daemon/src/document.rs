@@ -0,0 +1,144 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Conversion of submitted documents into print jobs
+
+use clap::{Arg, ArgMatches};
+use serde::Deserialize;
+
+use erika_3004::{
+    Encoding, LineSpacing, Markdown, PageLayout, PaperSize, Pitch, PrintJob, Result, Substitution,
+};
+
+/// How the text of a job is interpreted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    #[default]
+    Text,
+    Markdown,
+}
+
+/// Document sent by a client as JSON. Only the text is required.
+#[derive(Debug, Deserialize)]
+pub struct Submission {
+    pub text: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub format: Format,
+}
+
+/// Layout that is applied to every job
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub layout: PageLayout,
+    /// Split plain text into pages, and wait for a new sheet after each of them
+    pub paginate: bool,
+    /// Character set of jobs received as bytes, None to detect Unicode and fall back to Windows-1252
+    pub encoding: Option<Encoding>,
+    pub substitution: Substitution,
+    /// Interpret data received on the raw port as ESC/P printer commands
+    pub escp: bool,
+}
+
+/// Arguments that select the layout of the printed jobs
+pub fn settings_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("paginate")
+            .long("paginate")
+            .help("Split text into pages, and wait for a key press after each sheet"),
+        Arg::new("paper")
+            .long("paper")
+            .help("Paper size, used to compute the number of lines and characters that fit on a sheet")
+            .possible_values(PaperSize::names())
+            .ignore_case(true)
+            .default_value("a4"),
+        Arg::new("pitch")
+            .long("pitch")
+            .help("Characters per inch")
+            .possible_values(Pitch::NAMES)
+            .default_value("10"),
+        Arg::new("line-spacing")
+            .long("line-spacing")
+            .help("Line spacing")
+            .possible_values(LineSpacing::NAMES)
+            .default_value("1"),
+        Arg::new("encoding")
+            .long("encoding")
+            .help("Character set of jobs received over the network. By default, Unicode is detected and anything else is read as Windows-1252.")
+            .possible_values(["auto"].into_iter().chain(Encoding::NAMES))
+            .ignore_case(true)
+            .default_value("auto"),
+        Arg::new("substitution")
+            .long("substitution")
+            .help("What to do with characters the typewriter can't print: print question marks, print similar characters, or refuse the job")
+            .possible_values(Substitution::NAMES)
+            .default_value("question-mark"),
+        Arg::new("escp")
+            .long("escp")
+            .help("Interpret data received on the raw port as ESC/P printer commands instead of text"),
+    ]
+}
+
+impl Settings {
+    pub fn from_args(args: &ArgMatches) -> Settings {
+        let paper = args
+            .value_of("paper")
+            .and_then(PaperSize::from_name)
+            .unwrap_or(PaperSize::A4);
+        let pitch = args
+            .value_of("pitch")
+            .and_then(Pitch::from_name)
+            .unwrap_or(Pitch::Chars10PerInch);
+        let line_spacing = args
+            .value_of("line-spacing")
+            .and_then(LineSpacing::from_name)
+            .unwrap_or(LineSpacing::Single);
+
+        Settings {
+            layout: PageLayout::new(&paper, pitch, line_spacing),
+            paginate: args.is_present("paginate"),
+            encoding: args.value_of("encoding").and_then(Encoding::from_name),
+            substitution: args
+                .value_of("substitution")
+                .and_then(Substitution::from_name)
+                .unwrap_or(Substitution::QuestionMark),
+            escp: args.is_present("escp"),
+        }
+    }
+
+    /// Convert text received as bytes to Unicode
+    pub fn decode(&self, data: &[u8]) -> String {
+        let encoding = self
+            .encoding
+            .unwrap_or_else(|| Encoding::detect(data, Encoding::Windows1252));
+        encoding.decode(data)
+    }
+
+    /// Create the print job for a document, after applying the substitution policy
+    pub fn render(&self, text: &str, format: Format) -> Result<PrintJob> {
+        let text = self.substitution.apply(text)?;
+
+        if format == Format::Text && self.paginate {
+            return Ok(self.layout.render(&text));
+        }
+
+        let mut job = PrintJob::empty();
+        job.push_control(self.layout.pitch.control_code());
+        job.push_control(self.layout.line_spacing.control_code());
+        job.mark_prologue();
+        job.append(&match format {
+            Format::Text => PrintJob::new(&text),
+            Format::Markdown => Markdown {
+                margin_left: self.layout.margin_left,
+                ..Markdown::new(self.layout.text_width())
+            }
+            .render(&text),
+        });
+        Ok(job)
+    }
+}
@@ -0,0 +1,327 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! REST API for web dashboards and scripts. All responses are JSON.
+//!
+//! ```text
+//! GET    /jobs           {"jobs": [...]}
+//! POST   /jobs           {"id": 1}          Body as for the socket: {"text": "Hello", "format": "markdown"},
+//!                                           or the document itself with the type text/plain or text/markdown
+//! GET    /jobs/1         {"job": {...}}
+//! DELETE /jobs/1         {"cancelled": true}
+//! POST   /bell           {"id": 2}          Optional body: {"duration_ms": 500}
+//! POST   /paper          {"id": 3}          Optional body: {"lines": 2} or {"steps": 120}, one line by default,
+//!                                           at most one page
+//! GET    /keys?since=4   {"keys": [...]}    Keys typed after the key press with the given id
+//! ```
+//!
+//! Ringing the bell and moving the paper are queued before all other jobs,
+//! so they happen as soon as the job that is printing has finished.
+//! Failed requests are answered with {"error": "..."} and a 4xx status code.
+
+use std::io::{self, Read};
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use erika_3004::PrintJob;
+
+use crate::document::{Format, Submission};
+use crate::{Daemon, MAX_JOB_SIZE};
+
+/// Priority of bell and paper jobs
+const IMMEDIATE: i32 = i32::MAX;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct Bell {
+    duration_ms: u64,
+}
+
+impl Default for Bell {
+    fn default() -> Bell {
+        Bell { duration_ms: 1000 }
+    }
+}
+
+/// Distance to move the paper, in lines of the configured line spacing or in steps of 1/240 inch
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PaperMove {
+    lines: Option<u32>,
+    steps: Option<u32>,
+}
+
+type Answer = (u16, Value);
+
+fn error(status: u16, message: impl ToString) -> Answer {
+    (status, json!({ "error": message.to_string() }))
+}
+
+/// Listen for HTTP requests
+pub fn bind(address: impl ToSocketAddrs) -> io::Result<Server> {
+    Server::http(address).map_err(io::Error::other)
+}
+
+/// Answer a single request
+pub fn handle_request(mut request: Request, daemon: &Daemon) -> io::Result<()> {
+    let (status, body) = route(&mut request, daemon);
+
+    let content_type =
+        Header::from_bytes("Content-Type", "application/json").expect("header is valid ASCII");
+    request.respond(
+        Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(content_type),
+    )
+}
+
+fn route(request: &mut Request, daemon: &Daemon) -> Answer {
+    let method = request.method().clone();
+    let url = request.url().to_owned();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (method, segments.as_slice()) {
+        (Method::Get, ["jobs"]) => (200, json!({ "jobs": daemon.queue.jobs() })),
+        (Method::Post, ["jobs"]) => submit(request, daemon),
+        (Method::Get, ["jobs", id]) => match id.parse().ok().and_then(|id| daemon.queue.job(id)) {
+            Some(job) => (200, json!({ "job": job })),
+            None => error(404, format!("There is no job {}", id)),
+        },
+        (Method::Delete, ["jobs", id]) => match id.parse() {
+            Ok(id) => (200, json!({ "cancelled": daemon.queue.cancel(id) })),
+            Err(_) => error(404, format!("There is no job {}", id)),
+        },
+        (Method::Post, ["bell"]) => ring_bell(request, daemon),
+        (Method::Post, ["paper"]) => move_paper(request, daemon),
+        (Method::Get, ["keys"]) => {
+            let since = query
+                .split('&')
+                .find_map(|parameter| parameter.strip_prefix("since="))
+                .unwrap_or("0");
+            match since.parse() {
+                Ok(since) => (200, json!({ "keys": daemon.keyboard.since(since) })),
+                Err(_) => error(400, format!("Invalid key press id {}", since)),
+            }
+        }
+        (_, ["jobs"] | ["jobs", _] | ["bell"] | ["paper"] | ["keys"]) => {
+            error(405, "Method not allowed")
+        }
+        _ => error(404, format!("Not found: {}", path)),
+    }
+}
+
+/// Read the whole body, unless it is larger than any job can be
+fn read_body(request: &mut Request) -> Result<Vec<u8>, Answer> {
+    let too_large = || {
+        error(
+            413,
+            format!("Requests can't be larger than {} bytes", MAX_JOB_SIZE),
+        )
+    };
+    if request.body_length().unwrap_or(0) > MAX_JOB_SIZE {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    Read::take(request.as_reader(), MAX_JOB_SIZE as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| error(400, e))?;
+    if body.len() > MAX_JOB_SIZE {
+        return Err(too_large());
+    }
+    Ok(body)
+}
+
+/// Parse the body of a request that may be left empty
+fn read_options<T: DeserializeOwned + Default>(request: &mut Request) -> Result<T, Answer> {
+    let body = read_body(request)?;
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(T::default());
+    }
+
+    serde_json::from_slice(&body).map_err(|e| error(400, format!("Invalid request: {}", e)))
+}
+
+/// Media type of the body, without parameters like the charset
+fn content_type(request: &Request) -> String {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Content-Type"))
+        .and_then(|header| header.value.as_str().split(';').next())
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+fn submit(request: &mut Request, daemon: &Daemon) -> Answer {
+    let content_type = content_type(request);
+    let body = match read_body(request) {
+        Ok(body) => body,
+        Err(answer) => return answer,
+    };
+
+    let submission = match content_type.as_str() {
+        "application/json" => match serde_json::from_slice(&body) {
+            Ok(submission) => submission,
+            Err(e) => return error(400, format!("Invalid request: {}", e)),
+        },
+        _ => Submission {
+            text: daemon.settings.decode(&body),
+            name: "http".to_owned(),
+            priority: 0,
+            format: match content_type.as_str() {
+                "text/markdown" => Format::Markdown,
+                _ => Format::Text,
+            },
+        },
+    };
+
+    match daemon.submit(&submission, true) {
+        Ok(id) => (202, json!({ "id": id })),
+        Err(e) => error(422, e),
+    }
+}
+
+fn ring_bell(request: &mut Request, daemon: &Daemon) -> Answer {
+    let bell: Bell = match read_options(request) {
+        Ok(bell) => bell,
+        Err(answer) => return answer,
+    };
+
+    let mut job = PrintJob::empty();
+    match job.push_bell(Duration::from_millis(bell.duration_ms)) {
+        Ok(()) => (
+            202,
+            json!({ "id": daemon.queue.submit("bell", IMMEDIATE, job) }),
+        ),
+        Err(e) => error(422, e),
+    }
+}
+
+fn move_paper(request: &mut Request, daemon: &Daemon) -> Answer {
+    let distance: PaperMove = match read_options(request) {
+        Ok(distance) => distance,
+        Err(answer) => return answer,
+    };
+
+    let line_steps = daemon.settings.layout.line_spacing.paper_steps();
+    let steps = match distance {
+        PaperMove {
+            steps: Some(steps),
+            lines: None,
+        } => steps,
+        PaperMove {
+            lines: Some(lines),
+            steps: None,
+        } => lines.saturating_mul(line_steps),
+        PaperMove {
+            lines: None,
+            steps: None,
+        } => line_steps,
+        PaperMove { .. } => return error(400, "Either lines or steps can be given, not both"),
+    };
+
+    // Longer moves would keep the typewriter busy for minutes, without anything to show for it
+    let page_steps = (daemon.settings.layout.page_length as u32).saturating_mul(line_steps);
+    if steps > page_steps {
+        return error(
+            400,
+            format!(
+                "The paper can be moved by at most one page, which is {} steps",
+                page_steps
+            ),
+        );
+    }
+
+    let mut job = PrintJob::empty();
+    job.push_paper_move(steps);
+    (
+        202,
+        json!({ "id": daemon.queue.submit("paper", IMMEDIATE, job) }),
+    )
+}
+
+mod test {
+    #[test]
+    fn submit_and_list_jobs() -> std::io::Result<()> {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+        use std::thread;
+
+        use clap::Command;
+
+        use crate::document::{settings_args, Settings};
+        use crate::http::{bind, handle_request};
+        use crate::Daemon;
+
+        let daemon = Daemon::new(Settings::from_args(
+            &Command::new("test")
+                .args(settings_args())
+                .get_matches_from(["test"]),
+        ));
+        let server = bind("127.0.0.1:0")?;
+        let address = server.server_addr().to_ip().unwrap();
+
+        let request = |request: String| {
+            thread::spawn(move || -> std::io::Result<String> {
+                let mut stream = TcpStream::connect(address)?;
+                stream.write_all(request.as_bytes())?;
+                let mut response = String::new();
+                stream.read_to_string(&mut response)?;
+                Ok(response)
+            })
+        };
+
+        let body = r#"{"text": "Hallo", "name": "greeting", "format": "markdown"}"#;
+        let client = request(format!(
+            "POST /jobs HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        ));
+        handle_request(server.recv()?, &daemon)?;
+        let response = client.join().unwrap()?;
+        assert!(response.starts_with("HTTP/1.1 202"));
+        assert!(response.ends_with(r#"{"id":1}"#));
+
+        let client = request("POST /bell HTTP/1.1\r\nConnection: close\r\n\r\n".to_owned());
+        handle_request(server.recv()?, &daemon)?;
+        assert!(client.join().unwrap()?.ends_with(r#"{"id":2}"#));
+
+        let body = r#"{"lines": 4000000000}"#;
+        let client = request(format!(
+            "POST /paper HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        ));
+        handle_request(server.recv()?, &daemon)?;
+        assert!(client.join().unwrap()?.starts_with("HTTP/1.1 400"));
+
+        let client = request(format!(
+            "POST /jobs HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            crate::MAX_JOB_SIZE + 1
+        ));
+        handle_request(server.recv()?, &daemon)?;
+        assert!(client.join().unwrap()?.starts_with("HTTP/1.1 413"));
+
+        let client = request("GET /jobs/1 HTTP/1.1\r\nConnection: close\r\n\r\n".to_owned());
+        handle_request(server.recv()?, &daemon)?;
+        assert!(client.join().unwrap()?.contains(r#""name":"greeting""#));
+
+        let client = request("PUT /bell HTTP/1.1\r\nConnection: close\r\n\r\n".to_owned());
+        handle_request(server.recv()?, &daemon)?;
+        assert!(client.join().unwrap()?.starts_with("HTTP/1.1 405"));
+
+        // The bell is rung before the text is printed
+        let (id, _) = daemon.queue.next(std::time::Duration::ZERO).unwrap();
+        assert_eq!(id, 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Keys that were typed on the typewriter while no job was printing
+
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use erika_3004::InputEvent;
+
+/// Number of key presses that are remembered
+const HISTORY_LENGTH: usize = 256;
+
+/// What was typed, either a character or the name of a control code like "Enter"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Key {
+    Character(char),
+    ControlCode(String),
+}
+
+impl From<InputEvent> for Key {
+    fn from(event: InputEvent) -> Key {
+        match event {
+            InputEvent::Character(c) => Key::Character(c),
+            InputEvent::ControlCode(code) => Key::ControlCode(format!("{:?}", code)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyPress {
+    /// Counts up with every key press, so clients can ask for the ones they haven't seen yet
+    pub id: u64,
+    /// Milliseconds since the Unix epoch
+    pub time: u64,
+    #[serde(flatten)]
+    pub key: Key,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    presses: VecDeque<KeyPress>,
+    /// Clients that receive every key press as it happens
+    subscribers: Vec<mpsc::Sender<KeyPress>>,
+}
+
+/// Recent key presses, recorded by the thread that prints and read by clients
+#[derive(Default)]
+pub struct Keyboard {
+    state: Mutex<State>,
+}
+
+impl Keyboard {
+    pub fn new() -> Keyboard {
+        Keyboard::default()
+    }
+
+    pub fn record(&self, event: InputEvent) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let press = KeyPress {
+            id: state.next_id,
+            time,
+            key: event.into(),
+        };
+
+        // Clients that have disconnected are dropped
+        state
+            .subscribers
+            .retain(|subscriber| subscriber.send(press.clone()).is_ok());
+        state.presses.push_back(press);
+        if state.presses.len() > HISTORY_LENGTH {
+            state.presses.pop_front();
+        }
+    }
+
+    /// Remembered key presses that came after the one with the given id, oldest first
+    pub fn since(&self, id: u64) -> Vec<KeyPress> {
+        let state = self.state.lock().unwrap();
+        state
+            .presses
+            .iter()
+            .filter(|press| press.id > id)
+            .cloned()
+            .collect()
+    }
+
+    /// Receive all key presses from now on
+    #[cfg(feature = "websocket")]
+    pub fn subscribe(&self) -> mpsc::Receiver<KeyPress> {
+        let (sender, receiver) = mpsc::channel();
+        self.state.lock().unwrap().subscribers.push(sender);
+        receiver
+    }
+}
+
+mod test {
+    #[test]
+    fn key_presses_as_json() {
+        use crate::keyboard::Keyboard;
+        use erika_3004::{ControlCode, InputEvent};
+
+        let keyboard = Keyboard::new();
+        keyboard.record(InputEvent::Character('ä'));
+        keyboard.record(InputEvent::ControlCode(ControlCode::Enter));
+
+        let presses = keyboard.since(1);
+        assert_eq!(presses.len(), 1);
+        let json = serde_json::to_value(&presses[0]).unwrap();
+        assert_eq!(json["id"], 2);
+        assert_eq!(json["control_code"], "Enter");
+        assert_eq!(keyboard.since(0).len(), 2);
+    }
+}
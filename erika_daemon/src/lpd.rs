@@ -0,0 +1,368 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Line Printer Daemon protocol (RFC 1179), which `lpr` uses to send jobs to network printers.
+//!
+//! Jobs are accepted for any queue name. Text files are decoded with the configured character set,
+//! other file types like PostScript are refused, and so are jobs larger than a megabyte.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+use erika_3004::{ErikaError, PrintJob, Result};
+
+use crate::document::{Format, Settings};
+use crate::queue::Queue;
+use crate::{Daemon, CLIENT_TIMEOUT, MAX_JOB_SIZE};
+
+/// Acknowledgement of a command or file
+const ACCEPT: u8 = 0;
+const REFUSE: u8 = 1;
+
+/// Longest command line accepted, commands only contain a queue or file name and a few numbers
+const MAX_LINE_LENGTH: usize = 1024;
+
+/// Contents of a control file that matter for printing
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ControlFile {
+    /// Job name, or the name of the first file that was printed
+    name: Option<String>,
+    user: Option<String>,
+    /// Data files to print, with the letter of the print command. Files are repeated for every copy.
+    files: Vec<(char, String)>,
+}
+
+impl ControlFile {
+    fn parse(text: &str) -> ControlFile {
+        let mut control = ControlFile::default();
+        let mut source_name = None;
+
+        for line in text.lines() {
+            let mut characters = line.chars();
+            let command = match characters.next() {
+                Some(command) => command,
+                None => continue,
+            };
+            let operand = characters.as_str().to_owned();
+
+            match command {
+                'J' => control.name = Some(operand),
+                'N' => source_name = source_name.or(Some(operand)),
+                'P' => control.user = Some(operand),
+                'a'..='z' => control.files.push((command, operand)),
+                _ => {}
+            }
+        }
+
+        if control.name.as_deref().map_or(true, str::is_empty) {
+            control.name = source_name;
+        }
+
+        control
+    }
+
+    /// Name the job is listed with
+    fn job_name(&self) -> String {
+        match (&self.name, &self.user) {
+            (Some(name), Some(user)) => format!("{} ({})", name, user),
+            (Some(name), None) => name.clone(),
+            (None, Some(user)) => user.clone(),
+            (None, None) => "lpd".to_owned(),
+        }
+    }
+
+    /// Whether all data files the control file refers to have been received
+    fn is_complete(&self, files: &HashMap<String, Vec<u8>>) -> bool {
+        self.files.iter().all(|(_, name)| files.contains_key(name))
+    }
+
+    /// Create a job that prints all data files
+    fn render(&self, files: &HashMap<String, Vec<u8>>, settings: &Settings) -> Result<PrintJob> {
+        let mut job = PrintJob::empty();
+
+        for (kind, name) in &self.files {
+            // Formatted and literal text, and text that lpr should have passed through pr
+            if !matches!(kind, 'f' | 'l' | 'p') {
+                return Err(ErikaError::IO(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Only text can be printed, {} was sent as type {}",
+                        name, kind
+                    ),
+                )));
+            }
+
+            let text = settings.decode(&files[name]);
+            job.append(&settings.render(&text, Format::Text)?);
+        }
+
+        Ok(job)
+    }
+}
+
+/// Read a command line without its line feed. Returns None at the end of the stream.
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    let limit = MAX_LINE_LENGTH as u64 + 1;
+    if reader.take(limit).read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    } else if line.len() > MAX_LINE_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Command lines can't be longer than {} bytes",
+                MAX_LINE_LENGTH
+            ),
+        ));
+    }
+    Ok(Some(line))
+}
+
+/// Split a command line into the command code and its operands
+fn split_command(line: &[u8]) -> (u8, String) {
+    match line.split_first() {
+        Some((code, operands)) => (*code, String::from_utf8_lossy(operands).into_owned()),
+        None => (0, String::new()),
+    }
+}
+
+/// Answer a single command of a client
+pub fn handle_client(stream: TcpStream, daemon: &Daemon) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    let line = match read_line(&mut reader)? {
+        Some(line) => line,
+        None => return Ok(()),
+    };
+
+    let (command, operands) = split_command(&line);
+    match command {
+        // Print any waiting jobs, which happens anyway
+        0x01 => Ok(()),
+        0x02 => receive_job(&mut reader, &mut writer, &daemon.queue, &daemon.settings),
+        // Short and long queue state
+        0x03 | 0x04 => writer.write_all(queue_state(&daemon.queue).as_bytes()),
+        // Remove jobs, the operands are the queue, the user and the numbers of the jobs
+        0x05 => {
+            for id in operands.split_whitespace().skip(2) {
+                if let Ok(id) = id.parse() {
+                    daemon.queue.cancel(id);
+                }
+            }
+            Ok(())
+        }
+        _ => writer.write_all(&[REFUSE]),
+    }
+}
+
+/// Receive the control and data files of a job, and queue it once all of them have arrived
+fn receive_job(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    queue: &Queue,
+    settings: &Settings,
+) -> io::Result<()> {
+    writer.write_all(&[ACCEPT])?;
+
+    let mut control = None;
+    let mut files = HashMap::new();
+    // Bytes received for the job so far
+    let mut size = 0;
+
+    while let Some(line) = read_line(reader)? {
+        let (subcommand, operands) = split_command(&line);
+
+        let (count, name) = match (subcommand, operands.split_once(' ')) {
+            // Abort the job
+            (0x01, _) => return Ok(()),
+            (0x02 | 0x03, Some((count, name))) => match count.parse::<usize>() {
+                Ok(count) if count <= MAX_JOB_SIZE - size => (count, name.to_owned()),
+                _ => {
+                    writer.write_all(&[REFUSE])?;
+                    continue;
+                }
+            },
+            _ => {
+                writer.write_all(&[REFUSE])?;
+                continue;
+            }
+        };
+        writer.write_all(&[ACCEPT])?;
+
+        // The contents are followed by a zero octet
+        let mut data = Vec::new();
+        reader.take(count as u64 + 1).read_to_end(&mut data)?;
+        if data.len() <= count {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} ended before all of its {} bytes were sent", name, count),
+            ));
+        }
+        data.pop();
+        size += count;
+
+        if subcommand == 0x02 {
+            control = Some(ControlFile::parse(&String::from_utf8_lossy(&data)));
+        } else {
+            files.insert(name, data);
+        }
+
+        let result = match control.take() {
+            Some(control_file) if control_file.is_complete(&files) => {
+                let result = control_file.render(&files, settings).map(|mut job| {
+                    job.set_cancel_on_keypress(true);
+                    queue.submit(&control_file.job_name(), 0, job);
+                });
+                files.clear();
+                size = 0;
+                result
+            }
+            incomplete => {
+                control = incomplete;
+                Ok(())
+            }
+        };
+
+        match result {
+            Ok(()) => writer.write_all(&[ACCEPT])?,
+            Err(e) => {
+                eprintln!("Refused a job received over LPD: {}", e);
+                writer.write_all(&[REFUSE])?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// List of the jobs that have not finished yet
+fn queue_state(queue: &Queue) -> String {
+    let jobs: Vec<String> = queue
+        .jobs()
+        .into_iter()
+        .filter(|job| !job.status.is_finished())
+        .map(|job| format!("{}\t{}\t{}\n", job.id, job.status.name(), job.name))
+        .collect();
+
+    if jobs.is_empty() {
+        "no entries\n".to_owned()
+    } else {
+        jobs.concat()
+    }
+}
+
+mod test {
+    #[test]
+    fn receive_job_from_lpr() -> std::io::Result<()> {
+        use std::io::{Read, Write};
+        use std::net::{TcpListener, TcpStream};
+        use std::thread;
+        use std::time::Duration;
+
+        use clap::Command;
+
+        use crate::document::{settings_args, Settings};
+        use crate::lpd::handle_client;
+        use crate::queue::Status;
+        use crate::Daemon;
+
+        let daemon = Daemon::new(Settings::from_args(
+            &Command::new("test")
+                .args(settings_args())
+                .get_matches_from(["test"]),
+        ));
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let client = thread::spawn(move || -> std::io::Result<Vec<u8>> {
+            let control = "Hhost\nPjbb\nJletter\nldfA001host\nNletter.txt\n";
+            let data = b"Gr\xFC\xDFe\n";
+
+            let mut stream = TcpStream::connect(address)?;
+            stream.write_all(b"\x02erika\n")?;
+            stream.write_all(format!("\x03{} dfA001host\n", data.len()).as_bytes())?;
+            stream.write_all(data)?;
+            stream.write_all(b"\0")?;
+            stream.write_all(format!("\x02{} cfA001host\n", control.len()).as_bytes())?;
+            stream.write_all(control.as_bytes())?;
+            stream.write_all(b"\0")?;
+            stream.shutdown(std::net::Shutdown::Write)?;
+
+            let mut acknowledgements = Vec::new();
+            stream.read_to_end(&mut acknowledgements)?;
+            Ok(acknowledgements)
+        });
+
+        let (stream, _) = listener.accept()?;
+        handle_client(stream, &daemon)?;
+        assert_eq!(client.join().unwrap()?, vec![0; 5]);
+
+        let jobs = daemon.queue.jobs();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].name, "letter (jbb)");
+        assert_eq!(jobs[0].status, Status::Queued);
+
+        let (_, job) = daemon.queue.next(Duration::ZERO).unwrap();
+        assert_eq!(
+            job.data(),
+            [b"\x88\x85", erika_3004::PrintJob::new("Grüße\n").data()].concat()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn refuse_oversized_files() -> std::io::Result<()> {
+        use clap::Command;
+
+        use crate::document::{settings_args, Settings};
+        use crate::lpd::{receive_job, ACCEPT, REFUSE};
+        use crate::{Daemon, MAX_JOB_SIZE};
+
+        let daemon = Daemon::new(Settings::from_args(
+            &Command::new("test")
+                .args(settings_args())
+                .get_matches_from(["test"]),
+        ));
+
+        let request = format!(
+            "\x03{} dfA001host\n\x03{} dfA001host\n",
+            usize::MAX,
+            MAX_JOB_SIZE + 1
+        );
+        let mut acknowledgements = Vec::new();
+        receive_job(
+            &mut request.as_bytes(),
+            &mut acknowledgements,
+            &daemon.queue,
+            &daemon.settings,
+        )?;
+
+        assert_eq!(acknowledgements, vec![ACCEPT, REFUSE, REFUSE]);
+        assert!(daemon.queue.jobs().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn refuse_long_lines() {
+        use crate::lpd::{read_line, MAX_LINE_LENGTH};
+
+        let mut request = vec![b'a'; MAX_LINE_LENGTH];
+        request.push(b'\n');
+        assert_eq!(
+            read_line(&mut request.as_slice()).unwrap(),
+            Some(vec![b'a'; MAX_LINE_LENGTH])
+        );
+
+        let request = vec![b'a'; MAX_LINE_LENGTH + 1];
+        assert!(read_line(&mut request.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,260 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Print spooler that owns the serial port of the typewriter,
+//! and prints the jobs of all clients one after another.
+
+use std::io;
+use std::net::{IpAddr, TcpListener};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use clap::{Arg, Command};
+
+use erika_3004::{ErikaError, JobOutcome, TypewriterInterface};
+
+mod document;
+#[cfg(feature = "http")]
+mod http;
+mod keyboard;
+mod lpd;
+mod queue;
+mod raw;
+mod socket;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+use document::{Settings, Submission};
+use keyboard::Keyboard;
+use queue::{Queue, Status};
+
+const SERIAL_DEVICE: &str = "/dev/ttyUSB0";
+const SOCKET_PATH: &str = "/tmp/erika.sock";
+/// Only clients on the same machine can connect to the TCP ports, unless another address is given
+const LISTEN_ADDRESS: &str = "127.0.0.1";
+
+/// How often the keyboard is checked for input while no job is printing
+const KEYBOARD_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Largest number of bytes accepted for a job, which takes days to print
+pub const MAX_JOB_SIZE: usize = 1024 * 1024;
+/// How long a client on a TCP port may stay silent, or not read answers, before it is dropped
+pub const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// State shared by the thread that prints and the threads that answer clients
+pub struct Daemon {
+    pub queue: Queue,
+    pub settings: Settings,
+    /// Keys typed on the typewriter while it was idle
+    pub keyboard: Keyboard,
+}
+
+impl Daemon {
+    pub fn new(settings: Settings) -> Daemon {
+        Daemon {
+            queue: Queue::new(),
+            settings,
+            keyboard: Keyboard::new(),
+        }
+    }
+
+    /// Render a document and add it to the queue. Returns the id of the job.
+    /// With cancel_on_keypress, typing on the typewriter aborts the job while it is printing.
+    pub fn submit(
+        &self,
+        submission: &Submission,
+        cancel_on_keypress: bool,
+    ) -> erika_3004::Result<u64> {
+        let mut job = self.settings.render(&submission.text, submission.format)?;
+        job.set_cancel_on_keypress(cancel_on_keypress);
+        Ok(self
+            .queue
+            .submit(&submission.name, submission.priority, job))
+    }
+}
+
+/// Answer every client that connects in its own thread
+fn serve<S: Send + 'static>(
+    connections: impl Iterator<Item = io::Result<S>>,
+    daemon: &Arc<Daemon>,
+    handle_client: fn(S, &Daemon) -> io::Result<()>,
+) {
+    for stream in connections {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+
+        let daemon = daemon.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_client(stream, &daemon) {
+                eprintln!("Connection to a client failed: {}", e);
+            }
+        });
+    }
+}
+
+/// Print queued jobs as long as the daemon is running, and record key presses in between.
+/// Pressing any key on the typewriter aborts the job that is printing, if it was submitted that way.
+/// Otherwise the key is recorded once the job has finished.
+fn print_jobs(interface: &mut TypewriterInterface, daemon: &Daemon) -> erika_3004::Result<()> {
+    // Only check for pending input while idle, waiting is done by the queue
+    interface.set_read_timeout(Duration::ZERO)?;
+
+    loop {
+        let (id, job) = match daemon.queue.next(KEYBOARD_POLL_INTERVAL) {
+            Some(next) => next,
+            None => {
+                record_key_presses(interface, &daemon.keyboard);
+                continue;
+            }
+        };
+
+        match job.run(interface).map(|report| report.outcome) {
+            Ok(JobOutcome::Completed) => daemon.queue.finish(id, Status::Done, None),
+            Ok(JobOutcome::Cancelled { .. }) => daemon.queue.finish(id, Status::Cancelled, None),
+            Err(e) => {
+                eprintln!("Job {} failed: {}", id, e);
+                daemon.queue.finish(id, Status::Failed, Some(e.to_string()));
+            }
+        }
+    }
+}
+
+/// Read all input that arrived since the last check
+fn record_key_presses(interface: &mut TypewriterInterface, keyboard: &Keyboard) {
+    loop {
+        match interface.read_character() {
+            Ok(Some(event)) => keyboard.record(event),
+            Ok(None) => break,
+            Err(ErikaError::UnknownCode(code)) => {
+                eprintln!("Ignoring unknown code {:#x} typed on the keyboard", code)
+            }
+            Err(e) => {
+                eprintln!("Failed to read from the keyboard: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+fn main() -> erika_3004::Result<()> {
+    let command = Command::new("erika-daemon")
+        .about("Print jobs submitted over a Unix domain socket, one after another")
+        .arg(
+            Arg::new("device")
+                .short('d')
+                .long("device")
+                .help("Serial device to use, usually /dev/ttyUSB0")
+                .default_value(SERIAL_DEVICE),
+        )
+        .arg(
+            Arg::new("socket")
+                .short('s')
+                .long("socket")
+                .help("Path of the socket clients submit jobs to")
+                .default_value(SOCKET_PATH),
+        )
+        .arg(
+            Arg::new("listen")
+                .long("listen")
+                .help("Address the TCP ports are opened on, 0.0.0.0 to accept clients from all networks")
+                .default_value(LISTEN_ADDRESS)
+                .validator(|value| value.parse::<IpAddr>()),
+        )
+        .arg(
+            Arg::new("lpd-port")
+                .long("lpd-port")
+                .takes_value(true)
+                .help("Accept jobs sent with lpr on this TCP port, usually 515")
+                .validator(|value| value.parse::<u16>()),
+        )
+        .arg(
+            Arg::new("raw-port")
+                .long("raw-port")
+                .takes_value(true)
+                .help("Print everything sent to this TCP port, usually 9100")
+                .validator(|value| value.parse::<u16>()),
+        )
+        .args(document::settings_args());
+    #[cfg(feature = "http")]
+    let command = command.arg(
+        Arg::new("http-port")
+            .long("http-port")
+            .takes_value(true)
+            .help("Serve the REST API on this TCP port")
+            .validator(|value| value.parse::<u16>()),
+    );
+    #[cfg(feature = "websocket")]
+    let command = command.arg(
+        Arg::new("websocket-port")
+            .long("websocket-port")
+            .takes_value(true)
+            .help("Send key presses to WebSocket clients connected to this TCP port, and print what they send")
+            .validator(|value| value.parse::<u16>()),
+    );
+    let matches = command.get_matches();
+
+    let device = matches
+        .value_of("device")
+        .expect("device should have a default value");
+    let socket_path = matches
+        .value_of("socket")
+        .expect("socket should have a default value");
+    let address: IpAddr = matches
+        .value_of("listen")
+        .expect("listen should have a default value")
+        .parse()
+        .expect("validated by clap");
+
+    let mut interface = TypewriterInterface::new(device)?;
+    interface.lock()?;
+
+    let daemon = Arc::new(Daemon::new(Settings::from_args(&matches)));
+
+    let listener = socket::bind(Path::new(socket_path))?;
+    {
+        let daemon = daemon.clone();
+        thread::spawn(move || serve(listener.incoming(), &daemon, socket::handle_client));
+    }
+
+    if let Some(port) = matches.value_of("lpd-port") {
+        let listener = TcpListener::bind((address, port.parse().expect("validated by clap")))?;
+        let daemon = daemon.clone();
+        thread::spawn(move || serve(listener.incoming(), &daemon, lpd::handle_client));
+    }
+
+    if let Some(port) = matches.value_of("raw-port") {
+        let listener = TcpListener::bind((address, port.parse().expect("validated by clap")))?;
+        let daemon = daemon.clone();
+        thread::spawn(move || serve(listener.incoming(), &daemon, raw::handle_client));
+    }
+
+    #[cfg(feature = "http")]
+    if let Some(port) = matches.value_of("http-port") {
+        let server = http::bind((address, port.parse().expect("validated by clap")))?;
+        let daemon = daemon.clone();
+        thread::spawn(move || {
+            serve(
+                server.incoming_requests().map(Ok),
+                &daemon,
+                http::handle_request,
+            )
+        });
+    }
+
+    #[cfg(feature = "websocket")]
+    if let Some(port) = matches.value_of("websocket-port") {
+        let listener = TcpListener::bind((address, port.parse().expect("validated by clap")))?;
+        let daemon = daemon.clone();
+        thread::spawn(move || serve(listener.incoming(), &daemon, websocket::handle_client));
+    }
+
+    print_jobs(&mut interface, &daemon)
+}
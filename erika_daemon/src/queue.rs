@@ -0,0 +1,290 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Jobs waiting for the typewriter, and the state of the jobs that were printed
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use erika_3004::{CancelHandle, PrintJob, ProgressHandle};
+
+/// Number of jobs that are remembered, finished jobs are forgotten first
+const HISTORY_LENGTH: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Queued,
+    Printing,
+    /// A sheet is full, and the job continues once a key is pressed on the typewriter
+    WaitingForPaper,
+    Done,
+    Cancelled,
+    Failed,
+}
+
+impl Status {
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Queued => "queued",
+            Status::Printing => "printing",
+            Status::WaitingForPaper => "waiting for paper",
+            Status::Done => "done",
+            Status::Cancelled => "cancelled",
+            Status::Failed => "failed",
+        }
+    }
+
+    pub fn is_finished(self) -> bool {
+        matches!(self, Status::Done | Status::Cancelled | Status::Failed)
+    }
+}
+
+/// State of a job as reported to clients
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: u64,
+    pub name: String,
+    pub priority: i32,
+    pub status: Status,
+    /// Number of encoded bytes that have been printed
+    pub written: usize,
+    /// Number of encoded bytes of the whole job
+    pub size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct Entry {
+    info: JobInfo,
+    cancel: CancelHandle,
+    progress: ProgressHandle,
+}
+
+impl Entry {
+    fn info(&self) -> JobInfo {
+        let mut info = self.info.clone();
+        if info.status == Status::Printing {
+            info.written = self.progress.written();
+            if self.progress.is_waiting_for_paper() {
+                info.status = Status::WaitingForPaper;
+            }
+        }
+
+        info
+    }
+}
+
+/// Job waiting to be printed. Higher priorities come first, then the order of submission.
+struct Pending {
+    priority: i32,
+    id: u64,
+    job: PrintJob,
+}
+
+impl Pending {
+    fn key(&self) -> (i32, Reverse<u64>) {
+        (self.priority, Reverse(self.id))
+    }
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Pending) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Pending) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Pending) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    pending: BinaryHeap<Pending>,
+    /// All remembered jobs in the order they were submitted
+    entries: VecDeque<Entry>,
+}
+
+impl State {
+    fn entry(&mut self, id: u64) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.info.id == id)
+    }
+
+    /// Forget the oldest finished jobs once there are too many
+    fn forget_finished(&mut self) {
+        while self.entries.len() > HISTORY_LENGTH {
+            match self
+                .entries
+                .iter()
+                .position(|entry| entry.info.status.is_finished())
+            {
+                Some(index) => self.entries.remove(index),
+                None => break,
+            };
+        }
+    }
+}
+
+/// Queue shared by the clients that submit jobs and the thread that prints them
+#[derive(Default)]
+pub struct Queue {
+    state: Mutex<State>,
+    available: Condvar,
+}
+
+impl Queue {
+    pub fn new() -> Queue {
+        Queue::default()
+    }
+
+    /// Add a job to the queue, and return its id
+    pub fn submit(&self, name: &str, priority: i32, job: PrintJob) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+
+        state.entries.push_back(Entry {
+            info: JobInfo {
+                id,
+                name: name.to_owned(),
+                priority,
+                status: Status::Queued,
+                written: 0,
+                size: job.data().len(),
+                error: None,
+            },
+            cancel: job.cancel_handle(),
+            progress: job.progress_handle(),
+        });
+        state.pending.push(Pending { priority, id, job });
+        state.forget_finished();
+
+        self.available.notify_one();
+        id
+    }
+
+    /// Wait up to the timeout for a job to be queued, and mark the job with the highest priority as printing
+    pub fn next(&self, timeout: Duration) -> Option<(u64, PrintJob)> {
+        let state = self.state.lock().unwrap();
+        let (mut state, _) = self
+            .available
+            .wait_timeout_while(state, timeout, |state| state.pending.is_empty())
+            .unwrap();
+
+        let Pending { id, job, .. } = state.pending.pop()?;
+        if let Some(entry) = state.entry(id) {
+            entry.info.status = Status::Printing;
+        }
+        Some((id, job))
+    }
+
+    /// Record how a job that was printing ended
+    pub fn finish(&self, id: u64, status: Status, error: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(entry) = state.entry(id) {
+            entry.info.written = entry.progress.written();
+            entry.info.status = status;
+            entry.info.error = error;
+        }
+        state.forget_finished();
+    }
+
+    /// Remove a queued job, or abort it if it is printing.
+    /// Returns false if the job is unknown or has already finished.
+    pub fn cancel(&self, id: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let status = match state.entry(id) {
+            Some(entry) => entry.info.status,
+            None => return false,
+        };
+
+        match status {
+            Status::Queued => {
+                state.pending.retain(|pending| pending.id != id);
+                if let Some(entry) = state.entry(id) {
+                    entry.info.status = Status::Cancelled;
+                }
+                true
+            }
+            Status::Printing | Status::WaitingForPaper => {
+                if let Some(entry) = state.entry(id) {
+                    entry.cancel.cancel();
+                }
+                true
+            }
+            Status::Done | Status::Cancelled | Status::Failed => false,
+        }
+    }
+
+    /// State of a single job
+    pub fn job(&self, id: u64) -> Option<JobInfo> {
+        let mut state = self.state.lock().unwrap();
+        state.entry(id).map(|entry| entry.info())
+    }
+
+    /// State of all remembered jobs, in the order they were submitted
+    pub fn jobs(&self) -> Vec<JobInfo> {
+        let state = self.state.lock().unwrap();
+        state.entries.iter().map(Entry::info).collect()
+    }
+}
+
+mod test {
+    #[test]
+    fn higher_priorities_first() {
+        use std::time::Duration;
+
+        use crate::queue::Queue;
+        use erika_3004::PrintJob;
+
+        let queue = Queue::new();
+        let low = queue.submit("low", 0, PrintJob::new("a"));
+        let high = queue.submit("high", 5, PrintJob::new("b"));
+        let second_low = queue.submit("low", 0, PrintJob::new("c"));
+
+        assert_eq!(queue.next(Duration::ZERO).unwrap().0, high);
+        assert_eq!(queue.next(Duration::ZERO).unwrap().0, low);
+        assert_eq!(queue.next(Duration::ZERO).unwrap().0, second_low);
+        assert!(queue.next(Duration::ZERO).is_none());
+    }
+
+    #[test]
+    fn cancel_queued_job() {
+        use std::time::Duration;
+
+        use crate::queue::{Queue, Status};
+        use erika_3004::PrintJob;
+
+        let queue = Queue::new();
+        let first = queue.submit("first", 0, PrintJob::new("a"));
+        let second = queue.submit("second", 0, PrintJob::new("b"));
+
+        assert!(queue.cancel(first));
+        assert!(!queue.cancel(first));
+        assert_eq!(queue.job(first).unwrap().status, Status::Cancelled);
+
+        let (id, _) = queue.next(Duration::ZERO).unwrap();
+        assert_eq!(id, second);
+        assert_eq!(queue.job(second).unwrap().status, Status::Printing);
+
+        queue.finish(second, Status::Done, None);
+        let statuses: Vec<Status> = queue.jobs().iter().map(|job| job.status).collect();
+        assert_eq!(statuses, vec![Status::Cancelled, Status::Done]);
+    }
+}
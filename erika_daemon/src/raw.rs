@@ -0,0 +1,99 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Raw printing on TCP port 9100, also known as JetDirect or AppSocket.
+//!
+//! Everything a client sends until it closes the connection is printed as one job.
+
+use std::io::{self, Read};
+use std::net::TcpStream;
+
+use erika_3004::style::{Bold, Underline};
+use erika_3004::Escp;
+
+use crate::document::Format;
+use crate::{Daemon, CLIENT_TIMEOUT, MAX_JOB_SIZE};
+
+/// Print everything a client sends, as text or as ESC/P printer commands
+pub fn handle_client(mut stream: TcpStream, daemon: &Daemon) -> io::Result<()> {
+    let name = match stream.peer_addr() {
+        Ok(address) => format!("raw from {}", address.ip()),
+        Err(_) => "raw".to_owned(),
+    };
+
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut data = Vec::new();
+    stream
+        .by_ref()
+        .take(MAX_JOB_SIZE as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.is_empty() {
+        return Ok(());
+    }
+    if data.len() > MAX_JOB_SIZE {
+        eprintln!(
+            "Refused a job received on the raw port: it is larger than {} bytes",
+            MAX_JOB_SIZE
+        );
+        return Ok(());
+    }
+
+    let settings = &daemon.settings;
+    if settings.escp {
+        let mut job = Escp::new(Bold::DoublePrint, Underline::Line).feed(&data);
+        job.set_cancel_on_keypress(true);
+        daemon.queue.submit(&name, 0, job);
+        return Ok(());
+    }
+
+    match settings.render(&settings.decode(&data), Format::Text) {
+        Ok(mut job) => {
+            job.set_cancel_on_keypress(true);
+            daemon.queue.submit(&name, 0, job);
+        }
+        Err(e) => eprintln!("Refused a job received on the raw port: {}", e),
+    }
+
+    Ok(())
+}
+
+mod test {
+    #[test]
+    fn print_stream_as_text() -> std::io::Result<()> {
+        use std::io::Write;
+        use std::net::{TcpListener, TcpStream};
+        use std::thread;
+        use std::time::Duration;
+
+        use clap::Command;
+
+        use crate::document::{settings_args, Settings};
+        use crate::raw::handle_client;
+        use crate::Daemon;
+
+        let daemon = Daemon::new(Settings::from_args(
+            &Command::new("test")
+                .args(settings_args())
+                .get_matches_from(["test", "--encoding", "cp437"]),
+        ));
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let client = thread::spawn(move || TcpStream::connect(address)?.write_all(b"\x84b\n"));
+
+        let (stream, _) = listener.accept()?;
+        handle_client(stream, &daemon)?;
+        client.join().unwrap()?;
+
+        let jobs = daemon.queue.jobs();
+        assert_eq!(jobs[0].name, "raw from 127.0.0.1");
+        assert_eq!(
+            daemon.queue.next(Duration::ZERO).unwrap().1.data(),
+            [b"\x88\x85", erika_3004::PrintJob::new("äb\n").data()].concat()
+        );
+        Ok(())
+    }
+}
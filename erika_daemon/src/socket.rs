@@ -0,0 +1,107 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! Job protocol on the Unix domain socket.
+//!
+//! Clients send one JSON object per line, and receive one JSON object per line in return:
+//!
+//! ```text
+//! {"command": "submit", "text": "Hello", "name": "greeting", "priority": 1, "format": "text"}
+//! {"id": 1}
+//! {"command": "status"}
+//! {"jobs": [{"id": 1, "name": "greeting", "priority": 1, "status": "printing", "written": 2, "size": 5}]}
+//! {"command": "status", "id": 1}
+//! {"job": {"id": 1, "name": "greeting", "priority": 1, "status": "done", "written": 5, "size": 5}}
+//! {"command": "cancel", "id": 1}
+//! {"cancelled": false}
+//! {"command": "keys", "since": 3}
+//! {"keys": [{"id": 4, "time": 1656789012345, "character": "a"}, {"id": 5, "time": 1656789012420, "control_code": "Enter"}]}
+//! ```
+//!
+//! Only text is required for submitting, the format is either text or markdown.
+//! Keys lists what was typed on the typewriter while it was idle, after the key press with the given id.
+//! Failed requests are answered with {"error": "..."}.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::document::Submission;
+use crate::Daemon;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Request {
+    Submit(Submission),
+    Status {
+        id: Option<u64>,
+    },
+    Cancel {
+        id: u64,
+    },
+    Keys {
+        #[serde(default)]
+        since: u64,
+    },
+}
+
+/// Listen on the socket path. A socket left behind by a daemon that is no longer running is replaced.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is already used by another daemon", path.display()),
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+
+    UnixListener::bind(path)
+}
+
+/// Answer the requests of a client until it disconnects
+pub fn handle_client(stream: UnixStream, daemon: &Daemon) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        writeln!(writer, "{}", answer(&line, daemon, true))?;
+    }
+
+    Ok(())
+}
+
+/// Response to a single request.
+/// With cancel_on_keypress, typing on the typewriter aborts submitted jobs while they are printing.
+pub fn answer(request: &str, daemon: &Daemon, cancel_on_keypress: bool) -> Value {
+    match serde_json::from_str(request) {
+        Ok(request) => respond(request, daemon, cancel_on_keypress),
+        Err(e) => json!({ "error": format!("Invalid request: {}", e) }),
+    }
+}
+
+fn respond(request: Request, daemon: &Daemon, cancel_on_keypress: bool) -> Value {
+    match request {
+        Request::Submit(submission) => match daemon.submit(&submission, cancel_on_keypress) {
+            Ok(id) => json!({ "id": id }),
+            Err(e) => json!({ "error": e.to_string() }),
+        },
+        Request::Status { id: Some(id) } => match daemon.queue.job(id) {
+            Some(job) => json!({ "job": job }),
+            None => json!({ "error": format!("There is no job {}", id) }),
+        },
+        Request::Status { id: None } => json!({ "jobs": daemon.queue.jobs() }),
+        Request::Cancel { id } => json!({ "cancelled": daemon.queue.cancel(id) }),
+        Request::Keys { since } => json!({ "keys": daemon.keyboard.since(since) }),
+    }
+}
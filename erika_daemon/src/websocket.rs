@@ -0,0 +1,125 @@
+// SPDX-FileCopyrightText: 2022 Jonah Brüchert <jbb@kaidan.im>
+//
+// SPDX-License-Identifier: EUPL-1.2
+
+//! WebSocket for browser apps that use the typewriter as a terminal.
+//!
+//! Every key typed on the typewriter is sent to all clients as soon as it arrives:
+//!
+//! ```text
+//! {"key": {"id": 4, "time": 1656789012345, "character": "a"}}
+//! {"key": {"id": 5, "time": 1656789012420, "control_code": "Enter"}}
+//! ```
+//!
+//! Clients send the same JSON requests as on the Unix domain socket, one per text message,
+//! and receive the response in a text message. Printing text looks like this:
+//!
+//! ```text
+//! {"command": "submit", "text": "Hello\n"}
+//! {"id": 1}
+//! ```
+//!
+//! Typing does not abort the jobs of WebSocket clients, unlike those of all other clients.
+//! Keys typed while a job is printing are reported once it has finished.
+
+use std::io;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tungstenite::{Error, Message, WebSocket};
+
+use crate::socket;
+use crate::Daemon;
+
+/// How long to wait for a message of the client before forwarding new key presses
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+fn send(websocket: &mut WebSocket<TcpStream>, message: Value) -> io::Result<()> {
+    websocket
+        .send(Message::text(message.to_string()))
+        .map_err(io::Error::other)
+}
+
+/// Forward key presses to a client and answer its requests, until it disconnects
+pub fn handle_client(stream: TcpStream, daemon: &Daemon) -> io::Result<()> {
+    let mut websocket = tungstenite::accept(stream).map_err(|e| io::Error::other(e.to_string()))?;
+    websocket.get_mut().set_read_timeout(Some(POLL_INTERVAL))?;
+    let key_presses = daemon.keyboard.subscribe();
+
+    loop {
+        match websocket.read() {
+            Ok(Message::Text(request)) => {
+                send(&mut websocket, socket::answer(&request, daemon, false))?
+            }
+            // Pings are answered by tungstenite
+            Ok(_) => {}
+            Err(Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(Error::ConnectionClosed) => return Ok(()),
+            Err(e) => return Err(io::Error::other(e)),
+        }
+
+        for press in key_presses.try_iter() {
+            send(&mut websocket, json!({ "key": press }))?;
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn print_and_receive_keys() -> std::io::Result<()> {
+        use std::net::{TcpListener, TcpStream};
+        use std::thread;
+
+        use clap::Command;
+        use erika_3004::{ControlCode, InputEvent};
+        use serde_json::Value;
+        use tungstenite::Message;
+
+        use crate::document::{settings_args, Settings};
+        use crate::websocket::handle_client;
+        use crate::Daemon;
+
+        let daemon = Daemon::new(Settings::from_args(
+            &Command::new("test")
+                .args(settings_args())
+                .get_matches_from(["test"]),
+        ));
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+
+        thread::scope(|scope| {
+            let server = scope.spawn(|| handle_client(listener.accept()?.0, &daemon));
+
+            let stream = TcpStream::connect(address)?;
+            let (mut client, _) = tungstenite::client(format!("ws://{}/", address), stream)
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            let receive = |client: &mut tungstenite::WebSocket<TcpStream>| -> Value {
+                let message = client.read().unwrap();
+                serde_json::from_str(message.to_text().unwrap()).unwrap()
+            };
+
+            client
+                .send(Message::text(r#"{"command": "submit", "text": "Hallo\n"}"#))
+                .unwrap();
+            assert_eq!(receive(&mut client)["id"], 1);
+
+            daemon
+                .keyboard
+                .record(InputEvent::ControlCode(ControlCode::Enter));
+            assert_eq!(receive(&mut client)["key"]["control_code"], "Enter");
+
+            client.close(None).unwrap();
+            while client.read().is_ok() {}
+            server.join().unwrap()
+        })?;
+
+        // Pitch and line spacing before the text
+        assert_eq!(daemon.queue.jobs()[0].size, 2 + 6);
+        Ok(())
+    }
+}